@@ -0,0 +1,51 @@
+//! Bundles the stock `models/` tree into the binary, so a release binary works without
+//! the source tree. The files are listed in `$OUT_DIR/bundled_models.rs` as
+//! `(<path relative to models/>, include_str!(<absolute path>))`.
+
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+        .map(|e| e.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn main() {
+    let root = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("models");
+    println!("cargo:rerun-if-changed={}", root.display());
+    let mut files = vec![];
+    collect(&root, &mut files);
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("bundled_models.rs");
+    let mut f = fs::File::create(out).unwrap();
+    writeln!(f, "pub static BUNDLED_MODELS: &[(&str, &str)] = &[").unwrap();
+    for path in files {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let rel = path.strip_prefix(&root).unwrap();
+        let rel: Vec<_> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        writeln!(
+            f,
+            "    ({:?}, include_str!({:?})),",
+            rel.join("/"),
+            path.display().to_string()
+        )
+        .unwrap();
+    }
+    writeln!(f, "];").unwrap();
+}
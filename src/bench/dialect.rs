@@ -0,0 +1,244 @@
+//! Lowering of the neutral query form to the SQL dialects of targets.
+//!
+//! A query is written once with the neutral constructs below, which are lowered per target:
+//!
+//! | neutral                      | JoinBase                                           | Timescale/PostgreSQL            |
+//! |------------------------------|----------------------------------------------------|---------------------------------|
+//! | `TIME_RANGE(col, 'a', 'b')`  | `col >= dt'a' and col < dt'b'`                     | `col >= 'a' and col < 'b'`      |
+//! | `WHERE TIME_RANGE(...)`      | `where parts <ymdh(a)>..<ymdh(b)> where ...`       | as above                        |
+//! | `BUCKET_DAY(col)`            | `date(col)`                                        | `time_bucket('1 day', col)`     |
+//! | `BUCKET_HOUR(col)`           | `ymdh(col)`                                        | `time_bucket('1 hour', col)`    |
+//!
+//! The range of `TIME_RANGE` is half-open, the bounds are `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`.
+//! For JoinBase, a `TIME_RANGE` right after `WHERE` also prunes the scanned hourly partitions.
+//! Queries without neutral constructs are left as they are.
+
+use crate::error::OidbsError;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    JoinBase,
+    Postgres,
+}
+
+const TIME_FMT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn invalid(s: &str) -> OidbsError {
+    OidbsError::InvalidArgs(format!("neutral query construct '{}'", s))
+}
+
+fn parse_bound(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim().strip_prefix('\'')?.strip_suffix('\'')?;
+    NaiveDateTime::parse_from_str(s, TIME_FMT)
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+}
+
+/// the byte range of a call and its comma separated arguments
+type Call<'a> = (usize, usize, Vec<&'a str>);
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Finds the call of the neutral function `name` from `from`, skipping quoted literals and
+/// identifiers which merely end with `name`. The arguments may contain nested calls.
+fn find_call<'a>(
+    sql: &'a str,
+    upper: &str,
+    name: &str,
+    from: usize,
+) -> Result<Option<Call<'a>>, OidbsError> {
+    let pat = format!("{}(", name);
+    let bytes = sql.as_bytes();
+    let mut quote = None;
+    let mut i = from;
+    let st = loop {
+        match (quote, bytes.get(i)) {
+            (_, None) => return Ok(None),
+            (Some(q), Some(&c)) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, Some(&c)) if c == b'\'' || c == b'"' => quote = Some(c),
+            (None, Some(_)) => {
+                if upper.as_bytes()[i..].starts_with(pat.as_bytes())
+                    && (i == 0 || !is_ident(bytes[i - 1]))
+                {
+                    break i;
+                }
+            }
+        }
+        i += 1;
+    };
+
+    let args_st = st + pat.len();
+    let mut args = vec![];
+    let (mut arg_st, mut depth) = (args_st, 0);
+    for (i, &c) in bytes.iter().enumerate().skip(args_st) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, b'\'' | b'"') => quote = Some(c),
+            (None, b'(') => depth += 1,
+            (None, b')') if depth > 0 => depth -= 1,
+            (None, b')') => {
+                args.push(sql[arg_st..i].trim());
+                return Ok(Some((st, i + 1, args)));
+            }
+            (None, b',') if depth == 0 => {
+                args.push(sql[arg_st..i].trim());
+                arg_st = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Err(invalid(&sql[st..]))
+}
+
+impl Dialect {
+    pub fn timestamp_literal(self, ts: NaiveDateTime) -> String {
+        let ts = ts.format(TIME_FMT);
+        match self {
+            Dialect::JoinBase => format!("dt'{}'", ts),
+            Dialect::Postgres => format!("'{}'", ts),
+        }
+    }
+
+    fn bucket(self, unit: &str, col: &str) -> String {
+        match (self, unit) {
+            (Dialect::JoinBase, "DAY") => format!("date({})", col),
+            (Dialect::JoinBase, _) => format!("ymdh({})", col),
+            (Dialect::Postgres, "DAY") => format!("time_bucket('1 day', {})", col),
+            (Dialect::Postgres, _) => format!("time_bucket('1 hour', {})", col),
+        }
+    }
+
+    /// Lowers the neutral constructs in `sql` to this dialect.
+    pub fn translate(self, sql: &str) -> Result<String, OidbsError> {
+        let mut sql = self.translate_time_ranges(sql)?;
+        for unit in ["DAY", "HOUR"] {
+            let name = format!("BUCKET_{}", unit);
+            while let Some((st, ed, args)) =
+                find_call(&sql, &sql.to_ascii_uppercase(), &name, 0)?
+            {
+                if args.len() != 1 || args[0].is_empty() {
+                    return Err(invalid(&sql[st..ed]));
+                }
+                let lowered = self.bucket(unit, args[0]);
+                sql.replace_range(st..ed, &lowered);
+            }
+        }
+        Ok(sql)
+    }
+
+    fn translate_time_ranges(self, sql: &str) -> Result<String, OidbsError> {
+        let upper = sql.to_ascii_uppercase();
+        let mut out = String::with_capacity(sql.len());
+        let mut pos = 0;
+        while let Some((st, ed, args)) = find_call(sql, &upper, "TIME_RANGE", pos)? {
+            let call = &sql[st..ed];
+            let (col, start, end) = match args[..] {
+                [col, start, end] if !col.is_empty() => (
+                    col,
+                    parse_bound(start).ok_or_else(|| invalid(call))?,
+                    parse_bound(end).ok_or_else(|| invalid(call))?,
+                ),
+                _ => return Err(invalid(call)),
+            };
+            if start >= end {
+                return Err(invalid(call));
+            }
+            let predicate = format!(
+                "{} >= {} and {} < {}",
+                col,
+                self.timestamp_literal(start),
+                col,
+                self.timestamp_literal(end)
+            );
+            let head = sql[pos..st].trim_end();
+            let after_where = head.len() >= 5
+                && head[head.len() - 5..].eq_ignore_ascii_case("where")
+                && !head[..head.len() - 5].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+            if self == Dialect::JoinBase && after_where {
+                // the partitions are hourly, the end of the range is exclusive
+                let ymdh = |ts: NaiveDateTime| ts.format("%Y%m%d%H").to_string();
+                out.push_str(&sql[pos..st]);
+                out.push_str(&format!(
+                    "parts {}..{} where {}",
+                    ymdh(start),
+                    ymdh(end - Duration::seconds(1)),
+                    predicate
+                ));
+            } else {
+                out.push_str(&sql[pos..st]);
+                out.push_str(&predicate);
+            }
+            pos = ed;
+        }
+        out.push_str(&sql[pos..]);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dialect;
+
+    #[test]
+    fn test_translate() {
+        let q = "SELECT BUCKET_DAY(pickup_datetime) AS day, avg(fare_amount) FROM nyct_lite WHERE TIME_RANGE(pickup_datetime, '2016-01-01', '2016-01-08') AND passenger_count = 1 GROUP BY day ORDER BY day";
+        assert_eq!(
+            Dialect::JoinBase.translate(q).unwrap(),
+            "SELECT date(pickup_datetime) AS day, avg(fare_amount) FROM nyct_lite WHERE parts 2016010100..2016010723 where pickup_datetime >= dt'2016-01-01 00:00:00' and pickup_datetime < dt'2016-01-08 00:00:00' AND passenger_count = 1 GROUP BY day ORDER BY day"
+        );
+        assert_eq!(
+            Dialect::Postgres.translate(q).unwrap(),
+            "SELECT time_bucket('1 day', pickup_datetime) AS day, avg(fare_amount) FROM nyct_lite WHERE pickup_datetime >= '2016-01-01 00:00:00' and pickup_datetime < '2016-01-08 00:00:00' AND passenger_count = 1 GROUP BY day ORDER BY day"
+        );
+
+        // not right after WHERE, so no partition pruning
+        let q = "select count(*) from t where v > 1 and time_range(ts, '2021-01-01 10:00:00', '2021-01-01 11:00:00') group by bucket_hour(ts)";
+        assert_eq!(
+            Dialect::JoinBase.translate(q).unwrap(),
+            "select count(*) from t where v > 1 and ts >= dt'2021-01-01 10:00:00' and ts < dt'2021-01-01 11:00:00' group by ymdh(ts)"
+        );
+
+        let plain = "select count(*) from t where parts 2016013112";
+        assert_eq!(Dialect::JoinBase.translate(plain).unwrap(), plain);
+        assert!(Dialect::Postgres
+            .translate("select 1 from t where TIME_RANGE(ts, '2016-01-02', '2016-01-01')")
+            .is_err());
+        assert!(Dialect::Postgres
+            .translate("select 1 from t where TIME_RANGE(ts, 2016)")
+            .is_err());
+        assert!(Dialect::Postgres.translate("select BUCKET_DAY(ts").is_err());
+    }
+
+    #[test]
+    fn test_translate_nested_and_quoted() {
+        // nested calls in the arguments
+        let q = "select BUCKET_DAY(coalesce(ts, created)) from t where TIME_RANGE(coalesce(ts, created), '2016-01-01', '2016-01-02')";
+        assert_eq!(
+            Dialect::Postgres.translate(q).unwrap(),
+            "select time_bucket('1 day', coalesce(ts, created)) from t where coalesce(ts, created) >= '2016-01-01 00:00:00' and coalesce(ts, created) < '2016-01-02 00:00:00'"
+        );
+
+        // identifiers ending with the neutral names and quoted literals are not calls
+        let q = "select MY_TIME_RANGE(ts, 1), my_bucket_day(ts) from t where name = 'TIME_RANGE(x)' and \"BUCKET_HOUR(ts)\" > 0";
+        assert_eq!(Dialect::JoinBase.translate(q).unwrap(), q);
+
+        // non-ASCII characters before the call
+        let q = "-- durée moyenne\nselect 1 as ÿ from t where TIME_RANGE(ts, '2016-01-01', '2016-01-02')";
+        assert_eq!(
+            Dialect::Postgres.translate(q).unwrap(),
+            "-- durée moyenne\nselect 1 as ÿ from t where ts >= '2016-01-01 00:00:00' and ts < '2016-01-02 00:00:00'"
+        );
+
+        // a quoted parenthesis does not end the call
+        let q = "select 1 from t where TIME_RANGE(ts, '2016-01-01', '2016-01-02') and v = ')'";
+        assert_eq!(
+            Dialect::Postgres.translate(q).unwrap(),
+            "select 1 from t where ts >= '2016-01-01 00:00:00' and ts < '2016-01-02 00:00:00' and v = ')'"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Duration-based runs and soak testing.
+//!
+//! Instead of a fixed number of rounds, queries are run until a duration expires,
+//! and a snapshot of QPS and latency is taken at every interval. Over a long soak run,
+//! the snapshots at the end are compared with the ones at the start to detect the drift
+//! of throughput or latency, e.g. caused by compactions or memory pressure of the server.
+
+use super::percentile;
+use crate::{
+    error::OidbsError,
+    results::{as_micros, IntervalSnapshot, SoakDrift},
+    target::QueryOutcome,
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// the max number of latency samples kept for the whole run, beyond which the samples are
+/// drawn by reservoir sampling, so the memory keeps bounded over hours
+const MAX_SAMPLES: usize = 100_000;
+
+/// Parses a duration like `90s`, `10m`, `2h` or `1d`.
+pub fn parse_duration(s: &str) -> Result<Duration, OidbsError> {
+    let invalid = || OidbsError::InvalidArgs(format!("duration '{}'", s));
+    let s = s.trim();
+    let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?);
+    let n: u64 = n.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "s" => n,
+        "m" => n * 60,
+        "h" => n * 3600,
+        "d" => n * 86400,
+        _ => return Err(invalid()),
+    };
+    if secs == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+struct State {
+    /// the index of the current interval
+    index: u32,
+    /// numbers of successful, errored and timed out queries in the current interval
+    counts: [u64; 3],
+    latencies: Vec<Duration>,
+    snapshots: Vec<IntervalSnapshot>,
+    samples: Vec<Duration>,
+    num_seen: u64,
+    rng: SmallRng,
+}
+
+/// Records the outcomes of queries from all threads into interval snapshots,
+/// each snapshot is printed once its interval is closed.
+pub(crate) struct Recorder {
+    label: &'static str,
+    start: Instant,
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+impl Recorder {
+    pub(crate) fn new(label: &'static str, interval: Duration, seed: u64) -> Self {
+        Recorder {
+            label,
+            start: Instant::now(),
+            interval,
+            state: Mutex::new(State {
+                index: 0,
+                counts: [0; 3],
+                latencies: vec![],
+                snapshots: vec![],
+                samples: vec![],
+                num_seen: 0,
+                rng: SmallRng::seed_from_u64(seed),
+            }),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub(crate) fn record(&self, outcome: &QueryOutcome, time: Duration) {
+        let index = (self.start.elapsed().as_secs_f64() / self.interval.as_secs_f64()) as u32;
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while state.index < index {
+            self.close(&mut state, self.interval);
+        }
+        match outcome {
+            QueryOutcome::Ok(_) => {
+                state.counts[0] += 1;
+                state.latencies.push(time);
+            }
+            QueryOutcome::Error(_) => state.counts[1] += 1,
+            QueryOutcome::Timeout => state.counts[2] += 1,
+        }
+    }
+
+    /// Closes the last interval, returns all snapshots and the kept latency samples.
+    pub(crate) fn finish(self) -> (Vec<IntervalSnapshot>, Vec<Duration>) {
+        let elapsed = self.start.elapsed();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = (elapsed.as_secs_f64() / self.interval.as_secs_f64()) as u32;
+        while state.index < index {
+            self.close(&mut state, self.interval);
+        }
+        let rest = elapsed.saturating_sub(self.interval * state.index);
+        if !rest.is_zero() {
+            self.close(&mut state, rest);
+        }
+        (
+            std::mem::take(&mut state.snapshots),
+            std::mem::take(&mut state.samples),
+        )
+    }
+
+    fn close(&self, state: &mut State, len: Duration) {
+        let mut sorted = std::mem::take(&mut state.latencies);
+        sorted.sort_unstable();
+        let [num_ok, num_errors, num_timeouts] = state.counts;
+        let mean_us = (!sorted.is_empty())
+            .then(|| as_micros(sorted.iter().sum::<Duration>() / sorted.len() as u32));
+        let snapshot = IntervalSnapshot {
+            start_s: (self.interval * state.index).as_secs_f64(),
+            len_s: len.as_secs_f64(),
+            qps: num_ok as f64 / len.as_secs_f64(),
+            num_ok,
+            num_errors,
+            num_timeouts,
+            mean_us,
+            p50_us: percentile(&sorted, 0.50).map(as_micros),
+            p99_us: percentile(&sorted, 0.99).map(as_micros),
+        };
+        println!(
+            "[{}][{:>7.0}s] QPS: {:.2}, mean: {}, p50: {}, p99: {}, errors: {}, timeouts: {}",
+            self.label,
+            snapshot.start_s + snapshot.len_s,
+            snapshot.qps,
+            fmt_us(snapshot.mean_us),
+            fmt_us(snapshot.p50_us),
+            fmt_us(snapshot.p99_us),
+            num_errors,
+            num_timeouts
+        );
+        state.snapshots.push(snapshot);
+        // reservoir sampling over all latencies of the run
+        for t in sorted {
+            state.num_seen += 1;
+            if state.samples.len() < MAX_SAMPLES {
+                state.samples.push(t);
+            } else {
+                let i = state.rng.gen_range(0..state.num_seen);
+                if (i as usize) < MAX_SAMPLES {
+                    state.samples[i as usize] = t;
+                }
+            }
+        }
+        state.counts = [0; 3];
+        state.index += 1;
+    }
+}
+
+fn fmt_us(us: Option<u64>) -> String {
+    us.map_or("n/a".to_string(), |us| format!("{}us", us))
+}
+
+/// Detects the drift over a soak run, by comparing the first quarter of the full-length
+/// snapshots with the last quarter. `None` if there are less than 4 full-length snapshots.
+pub(crate) fn detect_drift(snapshots: &[IntervalSnapshot], threshold: f64) -> Option<SoakDrift> {
+    let max_len = snapshots.iter().map(|s| s.len_s).fold(0.0, f64::max);
+    let full: Vec<&IntervalSnapshot> = snapshots
+        .iter()
+        .filter(|s| s.len_s >= max_len * 0.999)
+        .collect();
+    if full.len() < 4 {
+        return None;
+    }
+    let q = full.len() / 4;
+    let (head, tail) = (&full[..q], &full[full.len() - q..]);
+    let mean = |v: &[&IntervalSnapshot], f: fn(&IntervalSnapshot) -> Option<f64>| {
+        let vs: Vec<f64> = v.iter().filter_map(|s| f(s)).collect();
+        (!vs.is_empty()).then(|| vs.iter().sum::<f64>() / vs.len() as f64)
+    };
+    let change = |f: fn(&IntervalSnapshot) -> Option<f64>| match (mean(head, f), mean(tail, f)) {
+        (Some(h), Some(t)) if h > 0.0 => Some(t / h - 1.0),
+        _ => None,
+    };
+    let qps_change = change(|s| Some(s.qps));
+    let p99_change = change(|s| s.p99_us.map(|v| v as f64));
+    let drifted = [qps_change, p99_change]
+        .iter()
+        .flatten()
+        .any(|c| c.abs() > threshold);
+    Some(SoakDrift {
+        qps_change,
+        p99_change,
+        drifted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_drift, parse_duration};
+    use crate::results::IntervalSnapshot;
+    use std::time::Duration;
+
+    fn snapshot(i: usize, len_s: f64, qps: f64) -> IntervalSnapshot {
+        IntervalSnapshot {
+            start_s: i as f64 * 10.0,
+            len_s,
+            qps,
+            num_ok: (qps * len_s) as u64,
+            num_errors: 0,
+            num_timeouts: 0,
+            mean_us: Some(1000),
+            p50_us: Some(900),
+            p99_us: Some(2000),
+        }
+    }
+
+    #[test]
+    fn test_soak() {
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("m").is_err());
+
+        // steady, the short last interval is ignored
+        let mut steady: Vec<_> = (0..8).map(|i| snapshot(i, 10.0, 100.0)).collect();
+        steady.push(snapshot(8, 0.5, 2.0));
+        let d = detect_drift(&steady, 0.1).unwrap();
+        assert!(!d.drifted);
+        assert_eq!(d.qps_change, Some(0.0));
+
+        // the throughput degrades by 30% over time
+        let degrading: Vec<_> = (0..8)
+            .map(|i| snapshot(i, 10.0, 100.0 - 5.0 * i as f64))
+            .collect();
+        let d = detect_drift(&degrading, 0.1).unwrap();
+        assert!(d.drifted);
+        assert!(d.qps_change.unwrap() < -0.3);
+
+        assert!(detect_drift(&steady[..3], 0.1).is_none());
+    }
+}
@@ -0,0 +1,129 @@
+//! Builder-style configs for embedding OIDBS as a library.
+//!
+//! The CLI args of `gen`, `import` and `bench` are the configs of the library too.
+//! A config is created with the CLI defaults, and every option is set by
+//! the method of the same name, e.g.
+//!
+//! ```no_run
+//! use oidbs::{bench::{Bench, QueryRequestor}, gen::{Gen, Generator}, model::bundled_models};
+//!
+//! # fn main() -> oidbs::error::OidbsResult<()> {
+//! let models = bundled_models()?;
+//! let gen = Gen::new("/tmp/dataset")?.workers(4).interval_per_worker_sec(60);
+//! let stats = Generator::new(gen, models.clone())?.run()?;
+//! assert!(stats.num_lines["pstations"] > 0);
+//!
+//! // the reports are not printed, the result document is used instead
+//! let bench = Bench::new("timescale", "pstations")?.run_times(5).quiet(true);
+//! let result = QueryRequestor::new(bench, models)?.run()?;
+//! assert!(result.queries.iter().all(|q| q.num_errors == 0));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{OidbsError, OidbsResult};
+use clap::{Args, Command, FromArgMatches};
+
+/// Parses the CLI args `args` of the config `T`, every option not given takes its CLI default.
+pub(crate) fn cli_defaults<T: Args + FromArgMatches>(args: &[&str]) -> OidbsResult<T> {
+    let matches = T::augment_args(Command::new("oidbs"))
+        .try_get_matches_from(std::iter::once("oidbs").chain(args.iter().copied()))
+        .map_err(|e| OidbsError::InvalidArgs(e.to_string()))?;
+    T::from_arg_matches(&matches).map_err(|e| OidbsError::InvalidArgs(e.to_string()))
+}
+
+/// Generates the builder methods setting the options of a config,
+/// the options in `optional` are `Option`s and set with their values.
+macro_rules! builder_methods {
+    ($config:ty {
+        $($field:ident: $t:tt),* $(,)?
+    } $(optional {
+        $($opt:ident: $ot:tt),* $(,)?
+    })?) => {
+        impl $config {
+            $(builder_methods!(@set $field: $t);)*
+            $($(builder_methods!(@opt $opt: $ot);)*)?
+        }
+    };
+    (@set $field:ident: String) => {
+        #[doc = concat!("Sets `", stringify!($field), "`, see the CLI option of the same name.")]
+        pub fn $field(mut self, v: impl Into<String>) -> Self {
+            self.$field = v.into();
+            self
+        }
+    };
+    (@set $field:ident: $t:ty) => {
+        #[doc = concat!("Sets `", stringify!($field), "`, see the CLI option of the same name.")]
+        pub fn $field(mut self, v: $t) -> Self {
+            self.$field = v;
+            self
+        }
+    };
+    (@opt $field:ident: String) => {
+        #[doc = concat!("Sets `", stringify!($field), "`, see the CLI option of the same name.")]
+        pub fn $field(mut self, v: impl Into<String>) -> Self {
+            self.$field = Some(v.into());
+            self
+        }
+    };
+    (@opt $field:ident: $t:ty) => {
+        #[doc = concat!("Sets `", stringify!($field), "`, see the CLI option of the same name.")]
+        pub fn $field(mut self, v: $t) -> Self {
+            self.$field = Some(v);
+            self
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bench::Bench,
+        builder::cli_defaults,
+        gen::Gen,
+        import::Import,
+        sampler::SamplerArgs,
+        suite::{BenchSpec, GenSpec, ImportSpec},
+    };
+
+    #[test]
+    fn test_builders() {
+        let gen = Gen::new("/tmp/dataset")
+            .unwrap()
+            .workers(4)
+            .out_of_order(true);
+        let spec = GenSpec::default();
+        assert_eq!(gen.output_dir, "/tmp/dataset");
+        assert_eq!(gen.workers, 4);
+        assert!(gen.out_of_order);
+        assert_eq!(gen.timestamp_start, spec.timestamp_start);
+        assert_eq!(gen.format, spec.format);
+
+        let import = Import::new("/tmp/dataset")
+            .unwrap()
+            .target_kind("timescale")
+            .verify_columns("fare_amount");
+        assert_eq!(import.model_name, "pstations");
+        assert_eq!(import.target_kind, "timescale");
+        assert_eq!(import.verify_columns.as_deref(), Some("fare_amount"));
+        assert_eq!(import.verify_wait_secs, 60);
+
+        let bench = Bench::new("joinbase", "nyct_strip")
+            .unwrap()
+            .threads("1,2,4");
+        assert_eq!(bench.model_name, "nyct_strip");
+        assert_eq!(bench.run_times, 3);
+        assert_eq!(bench.threads.as_deref(), Some("1,2,4"));
+        assert_eq!(bench.sampler.sample_interval_ms, 1000);
+
+        // the defaults of the suite specs are the CLI ones
+        assert_eq!(GenSpec::default().workers, 1);
+        assert_eq!(ImportSpec::default().verify_wait_secs, 60);
+        assert_eq!(BenchSpec::default().seed, 666666);
+        let sampler: SamplerArgs = cli_defaults(&[]).unwrap();
+        assert_eq!(
+            SamplerArgs::default().sample_interval_ms,
+            sampler.sample_interval_ms
+        );
+    }
+}
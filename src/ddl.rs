@@ -0,0 +1,460 @@
+//! A parser of the DDL in the schema files of models.
+//!
+//! Only the metadata of tables is extracted: the qualified names, the columns with their types,
+//! the primary keys and the partitioning, from `create table` of JoinBase, PostgreSQL and
+//! ClickHouse, and from `create_hypertable` of TimescaleDB. Other statements are skipped.
+
+use crate::error::OidbsError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// an identifier or a keyword, unquoted
+    Ident(String),
+    /// a single-quoted string literal, unquoted
+    Str(String),
+    Number(String),
+    Punct(char),
+    /// `=>` of named arguments
+    Arrow,
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, OidbsError> {
+    let mut tokens = vec![];
+    let cs: Vec<char> = sql.chars().collect();
+    let mut i = 0;
+    let unterminated = |what: &str| OidbsError::InvalidDdl(format!("unterminated {}", what));
+    while i < cs.len() {
+        let c = cs[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '-' if cs.get(i + 1) == Some(&'-') => {
+                while i < cs.len() && cs[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if cs.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i + 1 < cs.len() && !(cs[i] == '*' && cs[i + 1] == '/') {
+                    i += 1;
+                }
+                if i + 1 >= cs.len() {
+                    return Err(unterminated("comment"));
+                }
+                i += 2;
+            }
+            '\'' | '"' | '`' => {
+                // a doubled quote is an escaped one
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match cs.get(i) {
+                        None => return Err(unterminated("quote")),
+                        Some(&q) if q == c && cs.get(i + 1) == Some(&c) => {
+                            s.push(c);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&q) => {
+                            s.push(q);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(match c {
+                    '\'' => Token::Str(s),
+                    _ => Token::Ident(s),
+                });
+            }
+            '=' if cs.get(i + 1) == Some(&'>') => {
+                tokens.push(Token::Arrow);
+                i += 2;
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let st = i;
+                while i < cs.len() && (cs[i].is_alphanumeric() || cs[i] == '_' || cs[i] == '$') {
+                    i += 1;
+                }
+                let s: String = cs[st..i].iter().collect();
+                tokens.push(match c.is_ascii_digit() {
+                    true => Token::Number(s),
+                    false => Token::Ident(s),
+                });
+            }
+            _ => {
+                tokens.push(Token::Punct(c));
+                i += 1;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_keyword(t: Option<&Token>, kw: &str) -> bool {
+    matches!(t, Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw))
+}
+
+/// Splits the tokens at the top level separator, ignoring the ones in parentheses.
+fn split_top(tokens: &[Token], sep: char) -> Vec<&[Token]> {
+    let mut rt = vec![];
+    let mut depth = 0;
+    let mut st = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => depth -= 1,
+            Token::Punct(c) if *c == sep && depth == 0 => {
+                rt.push(&tokens[st..i]);
+                st = i + 1;
+            }
+            _ => {}
+        }
+    }
+    rt.push(&tokens[st..]);
+    rt.retain(|s| !s.is_empty());
+    rt
+}
+
+/// the index of the `)` closing the `(` at `open`
+fn closing(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        match t {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Renders the tokens back to SQL in a normalized spacing, e.g. `Decimal(10, 2)`.
+fn render(tokens: &[Token]) -> String {
+    let mut s = String::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let glued = matches!(t, Token::Punct('(' | ')' | ',' | '.'))
+            || matches!(
+                i.checked_sub(1).map(|p| &tokens[p]),
+                Some(Token::Punct('(' | '.')) | None
+            );
+        if !glued {
+            s.push(' ');
+        }
+        match t {
+            Token::Ident(v) | Token::Number(v) => s.push_str(v),
+            Token::Str(v) => s.push_str(&format!("'{}'", v.replace('\'', "''"))),
+            Token::Punct(c) => s.push(*c),
+            Token::Arrow => s.push_str("=>"),
+        }
+    }
+    s
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDef {
+    pub name: String,
+    /// the type as written, e.g. `Float32` or `TIMESTAMP WITHOUT TIME ZONE`
+    pub data_type: String,
+    pub not_null: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partitioning {
+    /// e.g. `ymdh` of JoinBase, `RANGE` of PostgreSQL, or `hypertable` of TimescaleDB
+    pub method: String,
+    /// the columns or arguments of the method, e.g. `ts`, `station_id` and `2`
+    /// of `create_hypertable('pstations', 'ts', 'station_id', 2)`
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableDef {
+    /// empty if the table is not qualified
+    pub database: String,
+    pub name: String,
+    /// in the order of the `create table`
+    pub columns: Vec<ColumnDef>,
+    pub primary_key: Vec<String>,
+    pub partitioning: Option<Partitioning>,
+}
+
+impl TableDef {
+    pub fn column(&self, name: &str) -> Option<&ColumnDef> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
+/// the words which end the type of a column definition
+const COLUMN_CONSTRAINTS: &[&str] = &[
+    "not",
+    "null",
+    "default",
+    "primary",
+    "references",
+    "unique",
+    "check",
+    "constraint",
+    "codec",
+    "comment",
+    "generated",
+    "collate",
+];
+
+/// the words which start a table constraint instead of a column
+const TABLE_CONSTRAINTS: &[&str] = &[
+    "primary",
+    "constraint",
+    "unique",
+    "foreign",
+    "check",
+    "index",
+    "key",
+    "exclude",
+];
+
+/// the names in the parenthesized list, e.g. `(a, b)` of `primary key (a, b)`
+fn names_in(tokens: &[Token]) -> Vec<String> {
+    tokens
+        .iter()
+        .filter_map(|t| match t {
+            Token::Ident(s) | Token::Str(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_create_table(stmt: &[Token]) -> Result<TableDef, OidbsError> {
+    let invalid = |msg: &str| OidbsError::InvalidDdl(format!("{} in `{}`", msg, render(stmt)));
+    // `create table`
+    let mut i = 2;
+    if is_keyword(stmt.get(i), "if")
+        && is_keyword(stmt.get(i + 1), "not")
+        && is_keyword(stmt.get(i + 2), "exists")
+    {
+        i += 3;
+    }
+    let mut parts = vec![];
+    while let Some(Token::Ident(s)) = stmt.get(i) {
+        parts.push(s.clone());
+        i += 1;
+        if stmt.get(i) != Some(&Token::Punct('.')) {
+            break;
+        }
+        i += 1;
+    }
+    let (database, name) = match &parts[..] {
+        [t] => (String::new(), t.clone()),
+        [d, t] => (d.clone(), t.clone()),
+        _ => return Err(invalid("no valid table name")),
+    };
+    let mut table = TableDef {
+        database,
+        name,
+        columns: vec![],
+        primary_key: vec![],
+        partitioning: None,
+    };
+
+    // a table without the column list, e.g. `create table t as ...` of ClickHouse
+    if stmt.get(i) != Some(&Token::Punct('(')) {
+        return Ok(table);
+    }
+    let close = closing(stmt, i).ok_or_else(|| invalid("unbalanced parentheses"))?;
+    for element in split_top(&stmt[i + 1..close], ',') {
+        let first = match &element[0] {
+            Token::Ident(s) => s.to_ascii_lowercase(),
+            _ => return Err(invalid("no valid column name")),
+        };
+        if TABLE_CONSTRAINTS.contains(&first.as_str()) {
+            if first == "primary" {
+                if let Some(open) = element.iter().position(|t| *t == Token::Punct('(')) {
+                    table.primary_key = names_in(&element[open..]);
+                }
+            }
+            continue;
+        }
+        let type_end = (1..element.len())
+            .find(|&j| {
+                COLUMN_CONSTRAINTS
+                    .iter()
+                    .any(|kw| is_keyword(element.get(j), kw))
+            })
+            .unwrap_or(element.len());
+        if type_end == 1 {
+            return Err(invalid("no type of a column"));
+        }
+        let rest = &element[type_end..];
+        let not_null = rest
+            .windows(2)
+            .any(|w| is_keyword(w.first(), "not") && is_keyword(w.get(1), "null"));
+        if rest.iter().any(|t| is_keyword(Some(t), "primary")) {
+            table.primary_key = vec![names_in(&element[..1]).remove(0)];
+        }
+        table.columns.push(ColumnDef {
+            name: names_in(&element[..1]).remove(0),
+            data_type: render(&element[1..type_end]),
+            not_null,
+        });
+    }
+
+    // the clauses after the column list
+    let tail = &stmt[close + 1..];
+    if let Some(p) = (0..tail.len())
+        .find(|&j| is_keyword(tail.get(j), "partition") && is_keyword(tail.get(j + 1), "by"))
+    {
+        let expr = &tail[p + 2..];
+        let end = (0..expr.len())
+            .find(|&j| {
+                ["order", "primary", "settings", "ttl"]
+                    .iter()
+                    .any(|kw| is_keyword(expr.get(j), kw))
+            })
+            .unwrap_or(expr.len());
+        let expr = &expr[..end];
+        if expr.is_empty() {
+            return Err(invalid("no partitioning expression"));
+        }
+        table.partitioning = Some(match expr {
+            [Token::Ident(method), Token::Punct('('), ..]
+                if closing(expr, 1) == Some(expr.len() - 1) =>
+            {
+                Partitioning {
+                    method: method.clone(),
+                    args: split_top(&expr[2..expr.len() - 1], ',')
+                        .iter()
+                        .map(|a| render(a))
+                        .collect(),
+                }
+            }
+            _ => Partitioning {
+                method: render(expr),
+                args: vec![],
+            },
+        });
+    }
+    Ok(table)
+}
+
+/// Applies `select create_hypertable('t', 'ts', ...)` of TimescaleDB to the table.
+fn apply_hypertable(stmt: &[Token], tables: &mut [TableDef]) -> Result<(), OidbsError> {
+    let open = match stmt.iter().position(|t| *t == Token::Punct('(')) {
+        Some(open) => open,
+        None => return Ok(()),
+    };
+    let close = closing(stmt, open).ok_or_else(|| {
+        OidbsError::InvalidDdl(format!("unbalanced parentheses in `{}`", render(stmt)))
+    })?;
+    // the positional arguments, the named ones after `=>` are skipped
+    let args: Vec<String> = split_top(&stmt[open + 1..close], ',')
+        .iter()
+        .filter(|a| !a.contains(&Token::Arrow))
+        .map(|a| match a {
+            [Token::Str(s)] => s.clone(),
+            _ => render(a),
+        })
+        .collect();
+    let target = args.first().map(|t| t.rsplit('.').next().unwrap_or(t));
+    match tables.iter_mut().find(|t| Some(t.name.as_str()) == target) {
+        Some(table) => {
+            table.partitioning = Some(Partitioning {
+                method: "hypertable".into(),
+                args: args[1..].to_vec(),
+            });
+            Ok(())
+        }
+        None => Err(OidbsError::InvalidDdl(format!(
+            "create_hypertable of an unknown table in `{}`",
+            render(stmt)
+        ))),
+    }
+}
+
+/// Parses all tables created in the schema file, in the order of their `create table`.
+pub fn parse_schema(sql: &str) -> Result<Vec<TableDef>, OidbsError> {
+    let tokens = tokenize(sql)?;
+    let mut tables = vec![];
+    for stmt in split_top(&tokens, ';') {
+        if is_keyword(stmt.first(), "create") && is_keyword(stmt.get(1), "table") {
+            tables.push(parse_create_table(stmt)?);
+        } else if is_keyword(stmt.first(), "select")
+            && stmt.iter().any(
+                |t| matches!(t, Token::Ident(s) if s.eq_ignore_ascii_case("create_hypertable")),
+            )
+        {
+            apply_hypertable(stmt, &mut tables)?;
+        }
+    }
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_schema, ColumnDef, Partitioning};
+
+    #[test]
+    fn test_parse_schema() {
+        let tables = parse_schema(
+            "-- DROP EXTENSION IF EXISTS timescaledb;\n\
+             CREATE EXTENSION IF NOT EXISTS timescaledb;\n\
+             drop table if exists pstations;\n\
+             create table pstations\n(\n    station_id integer,\n    sensor_value NUMERIC(10, 2),\n    \
+             ts TIMESTAMP WITHOUT TIME ZONE NOT NULL\n);\n\
+             SELECT create_hypertable('pstations', 'ts', 'station_id', 2, create_default_indexes=>FALSE);\n\
+             CREATE TABLE IF NOT EXISTS \"rates\"(\n    rate_code INTEGER PRIMARY KEY,\n    description TEXT default 'a;b'\n);\n\
+             INSERT INTO rates(rate_code, description) VALUES (1, 'standard rate');",
+        )
+        .unwrap();
+        assert_eq!(tables.len(), 2);
+        let t = &tables[0];
+        assert_eq!((t.database.as_str(), t.name.as_str()), ("", "pstations"));
+        assert_eq!(
+            t.columns[1],
+            ColumnDef {
+                name: "sensor_value".into(),
+                data_type: "NUMERIC(10, 2)".into(),
+                not_null: false,
+            }
+        );
+        assert_eq!(t.columns[2].data_type, "TIMESTAMP WITHOUT TIME ZONE");
+        assert!(t.columns[2].not_null);
+        assert_eq!(
+            t.partitioning,
+            Some(Partitioning {
+                method: "hypertable".into(),
+                args: vec!["ts".into(), "station_id".into(), "2".into()],
+            })
+        );
+        assert_eq!(tables[1].name, "rates");
+        assert_eq!(tables[1].primary_key, ["rate_code"]);
+        assert_eq!(tables[1].columns[1].data_type, "TEXT");
+
+        let t = &parse_schema(
+            "create table benchmark.t\n(\n    a UInt32,\n    b Nullable(String),\n    PRIMARY KEY (a)\n)\npartition BY ymdh(ts);",
+        )
+        .unwrap()[0];
+        assert_eq!((t.database.as_str(), t.name.as_str()), ("benchmark", "t"));
+        assert_eq!(t.columns.len(), 2);
+        assert_eq!(t.columns[1].data_type, "Nullable(String)");
+        assert_eq!(t.primary_key, ["a"]);
+        assert_eq!(t.partitioning.as_ref().unwrap().method, "ymdh");
+        assert_eq!(t.partitioning.as_ref().unwrap().args, ["ts"]);
+
+        // the table name without the column list
+        let t = &parse_schema("create table a123.b456\n").unwrap()[0];
+        assert_eq!((t.database.as_str(), t.name.as_str()), ("a123", "b456"));
+
+        assert!(parse_schema("create table (a int)").is_err());
+        assert!(parse_schema("create table t (a int").is_err());
+        assert!(parse_schema("create table t (a)").is_err());
+        assert!(parse_schema("create table t (a int) partition by").is_err());
+        assert!(parse_schema("select create_hypertable('x', 'ts')").is_err());
+        assert!(parse_schema("create table t (a text default 'x)").is_err());
+    }
+}
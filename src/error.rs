@@ -1,5 +1,5 @@
 use crate::mqtt_client;
-use std::io;
+use std::{io, path::Path, thread::ScopedJoinHandle};
 use thiserror::Error;
 
 pub type OidbsResult<T> = Result<T, OidbsError>;
@@ -8,14 +8,38 @@ pub type OidbsResult<T> = Result<T, OidbsError>;
 pub enum OidbsError {
     #[error("Unimplemented Model {0}")]
     UnimplementedModel(String),
+    #[error("Invalid model {0}: {1}")]
+    InvalidModel(String, String),
+    #[error("{0} problem(s) found in models")]
+    ModelProblems(usize),
+    #[error("Invalid model parameter {0}: {1}")]
+    InvalidModelParameter(String, String),
+    #[error("Invalid DDL: {0}")]
+    InvalidDdl(String),
     #[error("Generic errors: {0}")]
     Generic(&'static str),
     #[error("missing parameter {0}")]
     MissingArgs(String),
     #[error("Invalid {0} parameter")]
     InvalidArgs(String),
+    #[error("Unsupported {0}")]
+    Unsupported(String),
+    #[error("Query failed: {0}")]
+    QueryFailed(String),
+    #[error("Error rate {0:.4} of queries exceeds the max allowed {1:.4}")]
+    ErrorRateExceeded(f64, f64),
+    #[error("{0} regression(s) to the baseline found")]
+    Regression(usize),
+    #[error("Verification of the imported dataset failed: {0}")]
+    VerificationFailed(String),
     #[error("IO Error {0}")]
     IOError(#[from] io::Error),
+    #[error("IO Error on {0}: {1}")]
+    FileError(String, #[source] io::Error),
+    #[error("{0} panicked: {1}")]
+    WorkerPanicked(String, String),
+    #[error("{0}: {1}")]
+    Context(String, #[source] Box<OidbsError>),
     #[error("MQTT Error {0}")]
     MQTTError(#[from] mqtt_client::Error),
     #[error("PG Error {0}")]
@@ -31,3 +55,109 @@ pub enum OidbsError {
     #[error(transparent)]
     LibpqError(#[from] libpq::errors::Error),
 }
+
+impl OidbsError {
+    /// the IO error on the file at `path`
+    pub fn file(path: impl AsRef<Path>, e: io::Error) -> Self {
+        OidbsError::FileError(path.as_ref().display().to_string(), e)
+    }
+
+    /// the error of a worker thread which panicked, with the message of the panic
+    pub fn panicked(worker: impl Into<String>, payload: Box<dyn std::any::Any + Send>) -> Self {
+        let msg = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".into());
+        OidbsError::WorkerPanicked(worker.into(), msg)
+    }
+
+    /// The exit code of the process failed by the error, by which scripts tell the kind of failures:
+    ///
+    /// | code | failure |
+    /// |------|---------|
+    /// | 1 | internal errors, e.g. a panicked worker |
+    /// | 2 | invalid arguments, suite or profiles files |
+    /// | 3 | invalid or unimplemented models, schemas or model parameters |
+    /// | 4 | IO errors on local files |
+    /// | 5 | connecting to or talking with the target |
+    /// | 6 | failed queries |
+    /// | 7 | verification of the imported dataset failed |
+    /// | 8 | error rate of queries exceeded |
+    /// | 9 | regressions to the baseline found |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OidbsError::Context(_, e) => e.exit_code(),
+            OidbsError::Generic(_) | OidbsError::WorkerPanicked(..) => 1,
+            OidbsError::MissingArgs(_)
+            | OidbsError::InvalidArgs(_)
+            | OidbsError::Unsupported(_)
+            | OidbsError::VarError(_) => 2,
+            OidbsError::UnimplementedModel(_)
+            | OidbsError::InvalidModel(..)
+            | OidbsError::ModelProblems(_)
+            | OidbsError::InvalidModelParameter(..)
+            | OidbsError::InvalidDdl(_) => 3,
+            OidbsError::IOError(_)
+            | OidbsError::FileError(..)
+            | OidbsError::WalkdirError(_)
+            | OidbsError::CsvError(_)
+            | OidbsError::SerdeJson(_) => 4,
+            OidbsError::MQTTError(_) | OidbsError::PgError(_) | OidbsError::LibpqError(_) => 5,
+            OidbsError::QueryFailed(_) => 6,
+            OidbsError::VerificationFailed(_) => 7,
+            OidbsError::ErrorRateExceeded(..) => 8,
+            OidbsError::Regression(_) => 9,
+        }
+    }
+}
+
+/// Joins all the named workers, then returns their outputs or the first error,
+/// a panicked worker fails with its panic message instead of panicking the caller.
+pub fn join_workers<T>(
+    workers: Vec<(String, ScopedJoinHandle<'_, OidbsResult<T>>)>,
+) -> OidbsResult<Vec<T>> {
+    let results: Vec<_> = workers
+        .into_iter()
+        .map(|(worker, h)| {
+            h.join()
+                .map_err(|e| OidbsError::panicked(&worker, e))?
+                .context(|| worker)
+        })
+        .collect();
+    results.into_iter().collect()
+}
+
+/// Adds what was being done, e.g. the phase and the target, to the error of a result.
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, f: impl FnOnce() -> C) -> OidbsResult<T>;
+}
+
+impl<T, E: Into<OidbsError>> Context<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, f: impl FnOnce() -> C) -> OidbsResult<T> {
+        self.map_err(|e| OidbsError::Context(f().into(), Box::new(e.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Context, OidbsError, OidbsResult};
+
+    #[test]
+    fn test_exit_code() {
+        let r: OidbsResult<()> = Err(OidbsError::Regression(2));
+        let e = r
+            .context(|| "bench timescale")
+            .context(|| "phase 03")
+            .unwrap_err();
+        assert_eq!(e.exit_code(), 9);
+        assert_eq!(
+            e.to_string(),
+            "phase 03: bench timescale: 2 regression(s) to the baseline found"
+        );
+        let e = OidbsError::panicked("gen worker#1", Box::new("boom"));
+        assert_eq!(e.to_string(), "gen worker#1 panicked: boom");
+        assert_eq!(e.exit_code(), 1);
+        assert_eq!(OidbsError::InvalidArgs("x".into()).exit_code(), 2);
+    }
+}
@@ -1,13 +1,16 @@
 use crate::{
-    error::{OidbsError, OidbsResult},
+    builder::cli_defaults,
+    error::{join_workers, OidbsError, OidbsResult},
+    manifest::{self, DataFile, DatasetManifest},
     model::{GenRecords, Model, PStations},
+    sampler::{ResourceUsage, Sampler, SamplerArgs},
 };
 use chrono::{Duration, NaiveDateTime};
 use clap::Args;
 use rand::{rngs::SmallRng, SeedableRng};
 use serde_json::{Map, Value};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::OpenOptions,
     io::{BufWriter, Write},
     path::PathBuf,
@@ -15,38 +18,72 @@ use std::{
     thread,
 };
 
+/// the seed of the RNG generating the values, recorded in the manifest
+pub const GEN_SEED: u64 = 666666;
+
 #[derive(Args, Debug)]
 pub struct Gen {
     /// output generated data directory
-    output_dir: String,
+    pub(crate) output_dir: String,
 
     /// number of workers to start, different workers will generate different data files
     #[clap(short, long, default_value_t = 1)]
-    workers: u32,
+    pub(crate) workers: u32,
 
     /// the start timestamp for generated dataset
     #[clap(short, long, default_value_t = String::from("2021-01-01 00:00:01"))]
-    timestamp_start: String,
+    pub(crate) timestamp_start: String,
 
     /// interval per worker to gen, in seconds
     #[clap(short, long, default_value_t = 1)]
-    interval_per_worker_sec: u32,
+    pub(crate) interval_per_worker_sec: u32,
 
     /// the timestamp step for all dataset to gen, in seconds
     #[clap(short, long, default_value_t = 1)]
-    step_sec: u32,
+    pub(crate) step_sec: u32,
 
     /// format of output, options: csv, json
     #[clap(short = 'f', long, default_value_t = String::from("csv"))]
-    format: String,
+    pub(crate) format: String,
 
     /// make output out of order in bool, true if have
     #[clap(short, long)]
-    out_of_order: bool,
+    pub(crate) out_of_order: bool,
 
     /// model parameters, in the model specific json string format
     #[clap(short, long, default_value_t = String::from("{}"))]
+    pub(crate) model_parameters: String,
+
+    #[clap(flatten)]
+    pub(crate) sampler: SamplerArgs,
+}
+
+impl Gen {
+    /// the config generating into `output_dir`, with the CLI defaults
+    pub fn new(output_dir: &str) -> OidbsResult<Self> {
+        cli_defaults(&["--", output_dir])
+    }
+}
+
+builder_methods!(Gen {
+    workers: u32,
+    timestamp_start: String,
+    interval_per_worker_sec: u32,
+    step_sec: u32,
+    format: String,
+    out_of_order: bool,
     model_parameters: String,
+    sampler: SamplerArgs,
+});
+
+/// the result of generating datasets
+#[derive(Debug, Clone)]
+pub struct GenResult {
+    /// the number of generated lines of every model
+    pub num_lines: BTreeMap<String, u64>,
+    pub elapsed: std::time::Duration,
+    /// the sampled resource usage of the server, if sampled
+    pub resources: Option<ResourceUsage>,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +97,7 @@ pub struct Generator {
     pub format: String,
     pub out_of_order: bool,
     pub model_parameters: Map<String, Value>,
+    pub sampler: SamplerArgs,
 }
 
 pub fn gen_data(
@@ -75,7 +113,7 @@ pub fn gen_data(
     let out_of_order = g.out_of_order;
     log::debug!("out_of_order: {}", out_of_order);
     let output_dir = PathBuf::from(&g.path);
-    let mut rng: SmallRng = SmallRng::seed_from_u64(666666);
+    let mut rng: SmallRng = SmallRng::seed_from_u64(GEN_SEED);
     for model in g.models.iter_mut() {
         if model.has_completed {
             // log::debug!("to gen data for model: {:#?}...", &model);
@@ -86,7 +124,8 @@ pub fn gen_data(
                 .create_new(true)
                 .write(true)
                 .append(true)
-                .open(gen_file_path)?;
+                .open(&gen_file_path)
+                .map_err(|e| OidbsError::file(&gen_file_path, e))?;
             let mut buf = BufWriter::with_capacity(1024 * 1024, gen_file);
             let model_name = model.name.as_str();
             let ts0 = sdt + Duration::seconds(interval_per_worker_sec as i64 * i as i64);
@@ -108,8 +147,9 @@ pub fn gen_data(
                                 }
                                 // buf.write_all(&)?;
                                 for s in &ooo_buf {
-                                    buf.write_all(s.as_bytes()).unwrap();
-                                    buf.write(&[b'\n']);
+                                    buf.write_all(s.as_bytes())
+                                        .and_then(|_| buf.write_all(b"\n"))
+                                        .map_err(|e| OidbsError::file(&gen_file_path, e))?;
                                 }
                                 ooo_buf.clear();
                                 ooo_ct = CT_OOO - 1;
@@ -117,31 +157,46 @@ pub fn gen_data(
                                 ooo_ct -= 1;
                             }
                         }
-                        _ => unimplemented!(),
+                        _ => {
+                            return Err(OidbsError::UnimplementedModel(format!(
+                                "{} for gen",
+                                model_name
+                            )))
+                        }
                     },
                     "json" => match model_name {
                         "pstations" => {
                             PStations::gen_json_records(ts, &mut rng, &model_paras)?;
                         }
-                        _ => unimplemented!(),
+                        _ => {
+                            return Err(OidbsError::UnimplementedModel(format!(
+                                "{} for gen",
+                                model_name
+                            )))
+                        }
                     },
-                    format_str @ _ => {
-                        unimplemented!("format: {} does not supported", format_str)
+                    format_str => {
+                        return Err(OidbsError::Unsupported(format!(
+                            "format {} for gen",
+                            format_str
+                        )))
                     }
                 }
             }
             if ooo_buf.len() > 0 {
                 for s in &ooo_buf {
-                    buf.write_all(s.as_bytes()).unwrap();
-                    buf.write(&[b'\n']).unwrap();
+                    buf.write_all(s.as_bytes())
+                        .and_then(|_| buf.write_all(b"\n"))
+                        .map_err(|e| OidbsError::file(&gen_file_path, e))?;
                 }
                 ooo_buf.clear();
             }
-            buf.flush().unwrap();
+            buf.flush()
+                .map_err(|e| OidbsError::file(&gen_file_path, e))?;
 
             gen_stats
                 .lock()
-                .unwrap()
+                .map_err(|_| OidbsError::Generic("gen stats poisoned"))?
                 .entry(model_name.to_string())
                 .and_modify(|e| *e += num_all_lines)
                 .or_insert(num_all_lines);
@@ -156,7 +211,10 @@ impl Generator {
     pub fn new(gen: Gen, models: Vec<Model>) -> Result<Self, OidbsError> {
         let num_workers = gen.workers as u32;
         let parsed: Value = serde_json::from_str(&gen.model_parameters)?;
-        let model_parameters = parsed.as_object().unwrap().clone();
+        let model_parameters = parsed
+            .as_object()
+            .ok_or_else(|| OidbsError::InvalidArgs("model_parameters".into()))?
+            .clone();
         Ok(Generator {
             format: gen.format,
             path: gen.output_dir,
@@ -168,33 +226,84 @@ impl Generator {
             gen_step_sec: gen.step_sec,
             model_parameters,
             out_of_order: gen.out_of_order,
+            sampler: gen.sampler,
         })
     }
 
-    pub fn run(self) -> Result<(), OidbsError> {
+    pub fn run(self) -> Result<GenResult, OidbsError> {
+        let t = std::time::Instant::now();
         for model in self.models.iter() {
             model.ensure_gen_dir_clean(self.path.as_str())?;
         }
+        let sampler = Sampler::start(&self.sampler)?;
+        if let Some(sampler) = &sampler {
+            sampler.phase("gen");
+        }
         let gen_stats = Arc::new(Mutex::new(HashMap::new()));
         thread::scope(|s| {
-            for i in 0..self.num_workers {
-                let g = self.clone();
-                let gs = gen_stats.clone();
-                let mp = self.model_parameters.clone();
-                s.spawn(move || {
-                    if let Err(e) = gen_data(g, i, mp, gs) {
-                        panic!("error: {}", e.to_string())
-                    }
-                });
-            }
-        });
+            let workers = (0..self.num_workers)
+                .map(|i| {
+                    let g = self.clone();
+                    let gs = gen_stats.clone();
+                    let mp = self.model_parameters.clone();
+                    let h = s.spawn(move || gen_data(g, i, mp, gs));
+                    (format!("gen worker#{}", i), h)
+                })
+                .collect();
+            join_workers(workers)
+        })?;
 
         log::debug!("Generator run done!");
-        let gs = gen_stats.lock().unwrap();
-        for stat in &*gs {
-            println!("model {} gen, total lines: {}", stat.0, stat.1);
+        let num_lines: BTreeMap<_, _> = gen_stats
+            .lock()
+            .map_err(|_| OidbsError::Generic("gen stats poisoned"))?
+            .drain()
+            .collect();
+        for model in self.models.iter().filter(|m| m.has_completed) {
+            self.write_manifest(model)?;
         }
+        let resources = match sampler {
+            Some(sampler) => {
+                let mut usage = sampler.finish();
+                usage.set_rows("gen", num_lines.values().sum());
+                usage.report(self.sampler.resources_output.as_deref())?;
+                Some(usage)
+            }
+            None => None,
+        };
+
+        Ok(GenResult {
+            num_lines,
+            elapsed: t.elapsed(),
+            resources,
+        })
+    }
 
+    /// Writes the manifest of the generated dataset of the model into its directory.
+    fn write_manifest(&self, model: &Model) -> Result<(), OidbsError> {
+        let dir = manifest::model_dir(&self.path, &model.name);
+        let files = manifest::data_files(&dir)?
+            .iter()
+            .map(manifest::scan_file)
+            .collect::<Result<Vec<DataFile>, _>>()?;
+        let time_end = self.gen_start_ts
+            + Duration::seconds(
+                self.gen_interval_per_worker_sec as i64 * self.num_workers as i64,
+            );
+        let m = DatasetManifest {
+            generator_version: env!("CARGO_PKG_VERSION").to_string(),
+            model: model.name.clone(),
+            format: self.format.clone(),
+            seed: GEN_SEED,
+            model_parameters: Value::Object(self.model_parameters.clone()),
+            out_of_order: self.out_of_order,
+            time_start: self.gen_start_ts.format("%Y-%m-%d %H:%M:%S").to_string(),
+            time_end: time_end.format("%Y-%m-%d %H:%M:%S").to_string(),
+            num_rows: files.iter().map(|f| f.num_rows).sum(),
+            files,
+        };
+        m.write_to(&dir)?;
+        println!("manifest of model {} written to {}", model.name, dir.display());
         Ok(())
     }
 }
@@ -1,6 +1,24 @@
+#[macro_use]
+pub mod builder;
+pub mod ddl;
 pub mod error;
 pub mod gen;
 pub mod import;
+pub mod manifest;
+pub mod mixed;
 pub mod model;
+pub mod models;
+pub mod profile;
 pub mod mqtt_client;
+pub mod queries;
+pub mod regression;
+pub mod report;
+pub mod results;
+pub mod sampler;
+pub mod schema;
+pub mod sink;
+pub mod suite;
 pub mod bench;
+pub mod target;
+pub mod template;
+pub mod verify;
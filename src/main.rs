@@ -1,4 +1,4 @@
-use std::{env, path::PathBuf, time::Instant};
+use std::{env, time::Instant};
 
 use clap::{Parser, Subcommand};
 use oidbs::{
@@ -6,9 +6,14 @@ use oidbs::{
     error::OidbsResult,
     gen::{Gen, Generator},
     import::{Import, Importer},
-    model::read_from_path,
+    mixed::{Mixed, MixedRunner},
+    report::{Report, Reporter},
+    model::{load_models, model_sources},
+    models::{ModelInspector, Models},
+    profile::Profiles,
+    sink::{Sink, SinkRunner},
+    suite::{Run, SuiteRunner},
 };
-use tokio::runtime::Builder;
 
 #[derive(Parser)]
 #[clap(name = "Open IoT Database Benchmark Suite Tools")]
@@ -17,6 +22,14 @@ use tokio::runtime::Builder;
 #[clap(about = "Tools for Open IoT Database Benchmark Suite(OIDBS)", long_about = None)]
 #[clap(propagate_version = true)]
 struct Oidbs {
+    /// the directory of user models, in which every sub directory is a model, loaded on top of the ones bundled in the binary. A model replaces the bundled one of the same name. `$MODELS_ROOT/models` is used if not given
+    #[clap(long, global = true)]
+    models_dir: Option<String>,
+
+    /// the TOML file of the named connection profiles, by which the targets of `import`, `bench`, `mixed` and `run` are resolved. `$OIDBS_PROFILES` or `oidbs-profiles.toml` in the current directory is used if not given, and `OIDBS_PROFILE_<NAME>` variables add more
+    #[clap(long, global = true)]
+    profiles: Option<String>,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -29,59 +42,89 @@ enum Commands {
     Import(Import),
     /// Bench the performance of JoinBase and other databases(TimescaleDB/PostgreSQL) via kinds of queries and modes
     Bench(Bench),
+    /// Bench the queries of JoinBase while the dataset is being published via MQTT, and report the degradations of both sides
+    Mixed(Mixed),
+    /// Render charts(SVG or self-contained HTML) of query latencies, QPS and ratios to the baseline from bench result files
+    Report(Report),
+    /// Run a benchmark suite file, which declares the targets, models and the settings of gen, import and bench, with one result bundle of all phases
+    Run(Run),
+    /// Run a minimal MQTT broker stand-in, which counts and discards the published messages, to measure how fast the client host can publish
+    Sink(Sink),
+    /// List, inspect and validate the models, which are bundled in the binary or loaded from `--models-dir`
+    Models(Models),
 }
 
-fn main() -> OidbsResult<()> {
+fn main() {
     env_logger::init();
 
-    let oidbs = Oidbs::parse();
+    // the error is printed as a message instead of a debug dump,
+    // and the exit code tells the kind of the failure, see `OidbsError::exit_code`
+    if let Err(e) = run(Oidbs::parse()) {
+        eprintln!("error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
 
-    let models = load_models()?;
-    log::trace!("{:#?}", models);
+fn run(oidbs: Oidbs) -> OidbsResult<()> {
+    let models_dir = oidbs
+        .models_dir
+        .clone()
+        .or_else(|| env::var("MODELS_ROOT").ok().map(|r| r + "/models"));
+    // only the subcommands on models load them, so `sink` and `report` run without valid models
+    let models = || {
+        let models = load_models(models_dir.as_deref())?;
+        log::trace!("{:#?}", models);
+        OidbsResult::Ok(models)
+    };
 
     match oidbs.command {
         Commands::Gen(gen) => {
             log::trace!("gen: {:#?}", gen);
             let t = Instant::now();
-            let g = Generator::new(gen, models)?;
-            g.run()?;
+            let g = Generator::new(gen, models()?)?;
+            let result = g.run()?;
+            for (model, num_lines) in &result.num_lines {
+                println!("model {} gen, total lines: {}", model, num_lines);
+            }
             println!("total time in gen: {:?}", t.elapsed());
         }
         Commands::Import(import) => {
-            log::trace!("import: {:#?}", import);
-            let i = Importer::new(import, models)?;
-            let runtime = Builder::new_multi_thread()
-                .worker_threads(2)
-                .enable_io()
-                .build()?;
-            runtime.block_on(async { i.run().await.unwrap() });
+            let profiles = Profiles::load(oidbs.profiles.as_deref())?;
+            let i = Importer::new(import.profiles(profiles), models()?)?;
+            i.run()?;
         }
         Commands::Bench(query) => {
-            log::trace!("query: {:#?}", query);
-            let q = QueryRequestor::new(query, models)?;
+            let profiles = Profiles::load(oidbs.profiles.as_deref())?;
+            let q = QueryRequestor::new(query.profiles(profiles), models()?)?;
             log::trace!("QueryRequestor: {:#?}", q);
             q.run()?;
         }
+        Commands::Mixed(mixed) => {
+            let profiles = Profiles::load(oidbs.profiles.as_deref())?;
+            let m = MixedRunner::new(mixed.profiles(profiles), models()?)?;
+            m.run()?;
+        }
+        Commands::Report(report) => {
+            log::trace!("report: {:#?}", report);
+            let r = Reporter::new(report)?;
+            r.run()?;
+        }
+        Commands::Run(run) => {
+            log::trace!("run: {:#?}", run);
+            let profiles = Profiles::load(oidbs.profiles.as_deref())?;
+            let r = SuiteRunner::new(run.profiles(profiles), models()?)?;
+            r.run()?;
+        }
+        Commands::Sink(sink) => {
+            log::trace!("sink: {:#?}", sink);
+            SinkRunner::new(sink)?.run()?;
+        }
+        Commands::Models(m) => {
+            log::trace!("models: {:#?}", m);
+            let i = ModelInspector::new(m, model_sources(models_dir.as_deref())?);
+            i.run()?;
+        }
     }
 
     Ok(())
 }
-
-fn load_models() -> OidbsResult<Vec<oidbs::model::Model>> {
-    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    root.push("models");
-    let p = root.as_path();
-    let models = if p.exists() {
-        read_from_path(p.display().to_string())
-    } else {
-        let mut root = PathBuf::from(env::var("MODELS_ROOT")?);
-        root.push("models");
-        let p = root.as_path();
-        if p.exists() {
-            read_from_path(p.display().to_string())
-        } else {
-            panic!("Can not find a valid root dir of models. Consider specify the root dir of models via MODELS_ROOT enviroment variable!")
-        }
-    };
-    Ok(models)
-}
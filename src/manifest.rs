@@ -0,0 +1,227 @@
+//! The dataset manifest, `manifest.json` in the model directory of a generated dataset.
+//!
+//! `gen` writes the manifest, which records how the dataset is generated and what it contains.
+//! `import` validates the input files against it before importing, and verifies the imported
+//! rows with its row count. `bench` draws the random query arguments within its time range.
+
+use crate::error::OidbsError;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatasetManifest {
+    /// the version of OIDBS which generates the dataset
+    pub generator_version: String,
+    pub model: String,
+    pub format: String,
+    /// the seed of the RNG generating the values
+    pub seed: u64,
+    pub model_parameters: serde_json::Value,
+    pub out_of_order: bool,
+    /// the first timestamp of the dataset
+    pub time_start: String,
+    /// exclusive
+    pub time_end: String,
+    pub num_rows: u64,
+    pub files: Vec<DataFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataFile {
+    /// the file name in the model directory
+    pub name: String,
+    /// one row per line
+    pub num_rows: u64,
+    pub bytes: u64,
+    /// the 64-bit FNV-1a hash of the content, in hex
+    pub checksum: String,
+}
+
+/// the directory of the dataset of a model
+pub fn model_dir(data_dir: &str, model: &str) -> PathBuf {
+    Path::new(data_dir).join(model)
+}
+
+/// the data files in the model directory in the order of names, the manifest is excluded
+pub fn data_files<P: AsRef<Path>>(model_dir: P) -> Result<Vec<PathBuf>, OidbsError> {
+    let model_dir = model_dir.as_ref();
+    let mut files = vec![];
+    for e in fs::read_dir(model_dir).map_err(|e| OidbsError::file(model_dir, e))? {
+        let path = e.map_err(|e| OidbsError::file(model_dir, e))?.path();
+        if path.file_name().and_then(|n| n.to_str()) != Some(MANIFEST_FILE) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the file once for its size, number of lines and checksum.
+pub fn scan_file<P: AsRef<Path>>(path: P) -> Result<DataFile, OidbsError> {
+    read_file(path.as_ref(), true)
+}
+
+/// Reads the file once for its size and number of lines, without the checksum.
+pub fn count_file<P: AsRef<Path>>(path: P) -> Result<DataFile, OidbsError> {
+    read_file(path.as_ref(), false)
+}
+
+/// The size of the file from its metadata, without reading it, so no rows are counted.
+pub fn stat_file<P: AsRef<Path>>(path: P) -> Result<DataFile, OidbsError> {
+    let path = path.as_ref();
+    let meta = fs::metadata(path).map_err(|e| OidbsError::file(path, e))?;
+    Ok(DataFile {
+        name: file_name(path),
+        num_rows: 0,
+        bytes: meta.len(),
+        checksum: String::new(),
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn read_file(path: &Path, checksum: bool) -> Result<DataFile, OidbsError> {
+    let mut file = File::open(path).map_err(|e| OidbsError::file(path, e))?;
+    let mut buf = vec![0u8; 1 << 20];
+    let (mut bytes, mut num_rows) = (0u64, 0u64);
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut last = b'\n';
+    loop {
+        let n = file.read(&mut buf).map_err(|e| OidbsError::file(path, e))?;
+        if n == 0 {
+            break;
+        }
+        if checksum {
+            for &b in &buf[..n] {
+                hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        num_rows += buf[..n].iter().filter(|&&b| b == b'\n').count() as u64;
+        bytes += n as u64;
+        last = buf[n - 1];
+    }
+    // the last line without the trailing newline
+    if last != b'\n' {
+        num_rows += 1;
+    }
+    Ok(DataFile {
+        name: file_name(path),
+        num_rows,
+        bytes,
+        checksum: if checksum {
+            format!("{:016x}", hash)
+        } else {
+            String::new()
+        },
+    })
+}
+
+impl DatasetManifest {
+    /// Reads the manifest in the model directory, `None` if there is no manifest,
+    /// e.g. for an external dataset.
+    pub fn read_from<P: AsRef<Path>>(model_dir: P) -> Result<Option<Self>, OidbsError> {
+        let path = model_dir.as_ref().join(MANIFEST_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file = File::open(&path).map_err(|e| OidbsError::file(&path, e))?;
+        Ok(Some(serde_json::from_reader(file)?))
+    }
+
+    pub fn write_to<P: AsRef<Path>>(&self, model_dir: P) -> Result<(), OidbsError> {
+        let path = model_dir.as_ref().join(MANIFEST_FILE);
+        let file = File::create(&path).map_err(|e| OidbsError::file(&path, e))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Compares the scanned data files with the ones in the manifest, returns the differences.
+    pub fn validate(&self, scanned: &[DataFile]) -> Vec<String> {
+        let mut rt = vec![];
+        for f in &self.files {
+            match scanned.iter().find(|s| s.name == f.name) {
+                None => rt.push(format!("{} is missing", f.name)),
+                Some(s) if s.bytes != f.bytes || s.num_rows != f.num_rows => rt.push(format!(
+                    "{} has {} bytes and {} rows, but {} bytes and {} rows in the manifest",
+                    f.name, s.bytes, s.num_rows, f.bytes, f.num_rows
+                )),
+                Some(s) if s.checksum != f.checksum => {
+                    rt.push(format!("{} has a different checksum", f.name))
+                }
+                Some(_) => {}
+            }
+        }
+        for s in scanned {
+            if !self.files.iter().any(|f| f.name == s.name) {
+                rt.push(format!("{} is not in the manifest", s.name));
+            }
+        }
+        rt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_file, data_files, scan_file, stat_file, DatasetManifest, MANIFEST_FILE};
+
+    #[test]
+    fn test_scan_and_validate() {
+        let dir = std::env::temp_dir().join(format!("oidbs_manifest_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("000001.csv"), "a,1\nb,2\n").unwrap();
+        std::fs::write(dir.join("000000.csv"), "c,3\nd,4\ne,5").unwrap();
+        let files: Vec<_> = data_files(&dir)
+            .unwrap()
+            .iter()
+            .map(|p| scan_file(p).unwrap())
+            .collect();
+        assert_eq!(files[0].name, "000000.csv");
+        assert_eq!((files[0].num_rows, files[0].bytes), (3, 11));
+        assert_eq!(files[1].num_rows, 2);
+        assert_ne!(files[0].checksum, files[1].checksum);
+        let counted = count_file(dir.join("000000.csv")).unwrap();
+        assert_eq!((counted.num_rows, counted.bytes), (3, 11));
+        assert!(counted.checksum.is_empty());
+        let stat = stat_file(dir.join("000000.csv")).unwrap();
+        assert_eq!((stat.num_rows, stat.bytes), (0, 11));
+
+        let manifest = DatasetManifest {
+            generator_version: "test".into(),
+            model: "pstations".into(),
+            format: "csv".into(),
+            seed: 1,
+            model_parameters: serde_json::json!({}),
+            out_of_order: false,
+            time_start: "2021-01-01 00:00:01".into(),
+            time_end: "2021-01-01 00:00:02".into(),
+            num_rows: 5,
+            files: files.clone(),
+        };
+        manifest.write_to(&dir).unwrap();
+        // the manifest is not a data file
+        assert_eq!(data_files(&dir).unwrap().len(), 2);
+        assert!(dir.join(MANIFEST_FILE).is_file());
+        let read = DatasetManifest::read_from(&dir).unwrap().unwrap();
+        assert_eq!(read, manifest);
+        assert!(read.validate(&files).is_empty());
+
+        std::fs::write(dir.join("000001.csv"), "a,1\nb,3\n").unwrap();
+        let changed = scan_file(dir.join("000001.csv")).unwrap();
+        let m = read.validate(&[files[0].clone(), changed]);
+        assert_eq!(m, ["000001.csv has a different checksum"]);
+        assert_eq!(read.validate(&files[..1]).len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(DatasetManifest::read_from(&dir).unwrap().is_none());
+    }
+}
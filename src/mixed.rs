@@ -0,0 +1,262 @@
+use crate::{
+    bench::{Bench, Measurement, QueryEntry, QueryRequestor},
+    error::OidbsError,
+    import::{Import, Importer},
+    model::Model,
+    profile::Profiles,
+};
+use clap::Args;
+use comfy_table::{Cell, Row, Table};
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Args, Debug)]
+pub struct Mixed {
+    /// Input directory, which contains the dataset to be published into JoinBase while queries are running
+    input_dir: String,
+
+    /// JoinBase server url part for MQTT endpoint
+    #[clap(long, default_value_t = String::from("abc:abc@127.0.0.1:1883"))]
+    ib_srv_part_mqtt: String,
+
+    /// JoinBase server url part for pg wire protocol endpoint
+    #[clap(short, long, default_value_t = String::from("abc:abc@127.0.0.1:5433"))]
+    ib_srv_part_pg: String,
+
+    /// the model name to run, which allows users to run against a specified OIDBS data model. Options included of pstations, nyct
+    #[clap(short = 'n', long, default_value_t = String::from("pstations"))]
+    model_name: String,
+
+    /// the round times to formally run queries for the final performance measurement
+    #[clap(short = 'r', long, default_value_t = 3)]
+    run_times: u32,
+
+    /// to control the measurement mode of queries: `latency` or `concurrency`, same as the one in `bench`
+    #[clap(short = 'c', long, default_value_t = String::from("latency"))]
+    measurement_mode: String,
+
+    /// the times to run warm-up round for concurrency measurement mode
+    #[clap(short = 'w', long, default_value_t = 10)]
+    warmup_times: u32,
+
+    /// the number of concurrent running threads, this option is only valid for the `concurrency` measurement mode
+    #[clap(short = 'm', long, default_value_t = 24)]
+    num_concurrent_threads: usize,
+
+    /// the total rate to publish into JoinBase, in rows per second
+    #[clap(short = 'R', long, default_value_t = 100_000)]
+    ingest_rate: u64,
+
+    /// the number of rows in one batch for publishing data into JoinBase
+    #[clap(short = 'b', long, default_value_t = 1)]
+    num_rows_in_batch: i32,
+
+    /// the seconds to publish without any query, for measuring the idle ingest rate
+    #[clap(short = 's', long, default_value_t = 10)]
+    ingest_baseline_secs: u64,
+
+    /// the timeout of every query in milliseconds, 0 means no timeout
+    #[clap(long, default_value_t = 0)]
+    query_timeout_ms: u64,
+
+    /// the max allowed ratio of failed(errored or timed out) queries to all queries, beyond which the run exits with an error
+    #[clap(long, default_value_t = 0.0)]
+    max_error_rate: f64,
+
+    /// the seed of the RNG filling in the placeholders of query templates
+    #[clap(long, default_value_t = 666666)]
+    seed: u64,
+
+    /// the connection profiles, a profile named `joinbase` overrides the url parts
+    #[clap(skip)]
+    pub(crate) profiles: Profiles,
+}
+
+builder_methods!(Mixed { profiles: Profiles });
+
+/// Runs queries while devices keep publishing, which is the typical IoT scenario.
+///
+/// Only JoinBase is supported, because it is the only target ingesting via MQTT.
+/// The run has three phases:
+/// 1. queries without ingest, as the query baseline,
+/// 2. ingest without queries, as the ingest baseline,
+/// 3. queries with ingest at the configured rate.
+pub struct MixedRunner {
+    importer: Importer,
+    requestor: QueryRequestor,
+    ingest_rate: u64,
+    ingest_baseline: Duration,
+}
+
+impl MixedRunner {
+    pub fn new(mixed: Mixed, models: Vec<Model>) -> Result<Self, OidbsError> {
+        let import = Import::new(&mixed.input_dir)?
+            .ib_srv_part_mqtt(mixed.ib_srv_part_mqtt)
+            .ib_srv_part_pg(mixed.ib_srv_part_pg.clone())
+            .target_kind("joinbase")
+            .model_name(mixed.model_name.clone())
+            .import_data_only(true)
+            .num_rows_in_batch(mixed.num_rows_in_batch)
+            .skip_verify(true)
+            .verify_wait_secs(0)
+            .profiles(mixed.profiles.clone());
+        let bench = Bench::new("joinbase", &mixed.model_name)?
+            .ib_srv_part_pg(mixed.ib_srv_part_pg)
+            .run_times(mixed.run_times)
+            .measurement_mode(mixed.measurement_mode)
+            .warmup_times(mixed.warmup_times)
+            .num_concurrent_threads(mixed.num_concurrent_threads)
+            .query_timeout_ms(mixed.query_timeout_ms)
+            .max_error_rate(mixed.max_error_rate)
+            .seed(mixed.seed)
+            .dataset_dir(mixed.input_dir)
+            .profiles(mixed.profiles);
+        if mixed.ingest_rate == 0 {
+            return Err(OidbsError::InvalidArgs("ingest_rate".into()));
+        }
+        if mixed.ingest_baseline_secs == 0 {
+            return Err(OidbsError::InvalidArgs("ingest_baseline_secs".into()));
+        }
+
+        Ok(Self {
+            importer: Importer::new(import, models.clone())?,
+            requestor: QueryRequestor::new(bench, models)?,
+            ingest_rate: mixed.ingest_rate,
+            ingest_baseline: Duration::from_secs(mixed.ingest_baseline_secs),
+        })
+    }
+
+    pub fn run(self) -> Result<(), OidbsError> {
+        println!("[mixed] run queries without ingest");
+        let idle = self.requestor.measure(None)?;
+
+        println!(
+            "[mixed] run ingest without queries for {:?}",
+            self.ingest_baseline
+        );
+        let ((), idle_ingest_rate) = self.ingest_during(|| {
+            thread::sleep(self.ingest_baseline);
+            Ok(())
+        })?;
+
+        println!(
+            "[mixed] run queries with ingest at {} rows/s",
+            self.ingest_rate
+        );
+        let (loaded, loaded_ingest_rate) = self.ingest_during(|| self.requestor.measure(None))?;
+
+        match (&idle, &loaded) {
+            (Measurement::Latency(idle, _), Measurement::Latency(loaded, _)) => {
+                print_latency_report(idle, loaded)
+            }
+            (Measurement::Concurrency(idle), Measurement::Concurrency(loaded)) => {
+                println!(
+                    "query QPS without ingest: {:.2}, with ingest: {:.2}, degradation: {:+.2}%",
+                    idle.qps,
+                    loaded.qps,
+                    rate_degradation(idle.qps, loaded.qps)
+                );
+                println!(
+                    "query errors/timeouts without ingest: {}/{}, with ingest: {}/{}",
+                    idle.num_errors, idle.num_timeouts, loaded.num_errors, loaded.num_timeouts
+                );
+            }
+            _ => {
+                return Err(OidbsError::Unsupported(
+                    "sweep or duration-based run in mixed".into(),
+                ))
+            }
+        }
+        println!(
+            "ingest rate(rows/s) target: {}, without queries: {:.2}, with queries: {:.2}, degradation: {:+.2}%",
+            self.ingest_rate,
+            idle_ingest_rate,
+            loaded_ingest_rate,
+            rate_degradation(idle_ingest_rate, loaded_ingest_rate)
+        );
+
+        self.requestor.check_error_rate(&idle)?;
+        self.requestor.check_error_rate(&loaded)
+    }
+
+    /// Runs `f` while publishing at the configured rate,
+    /// returns the output of `f` and the achieved ingest rate in rows per second.
+    fn ingest_during<T>(
+        &self,
+        f: impl FnOnce() -> Result<T, OidbsError>,
+    ) -> Result<(T, f64), OidbsError> {
+        let stop = AtomicBool::new(false);
+        let published = AtomicU64::new(0);
+        thread::scope(|s| {
+            let publisher = s.spawn(|| {
+                self.importer
+                    .stream_at_rate(self.ingest_rate, &stop, &published)
+            });
+            let ts = Instant::now();
+            let ret = f();
+            let time = ts.elapsed();
+            stop.store(true, Ordering::Relaxed);
+            publisher
+                .join()
+                .map_err(|e| OidbsError::panicked("publishing thread", e))??;
+            let rate = published.load(Ordering::Relaxed) as f64 / time.as_secs_f64();
+            Ok((ret?, rate))
+        })
+    }
+}
+
+/// the increase of latency in percent, positive means slower
+fn latency_degradation(idle: Duration, loaded: Duration) -> f64 {
+    (loaded.as_secs_f64() / idle.as_secs_f64() - 1.0) * 100.0
+}
+
+/// the decrease of rate in percent, positive means slower
+fn rate_degradation(idle: f64, loaded: f64) -> f64 {
+    (1.0 - loaded / idle) * 100.0
+}
+
+fn print_latency_report(idle: &[QueryEntry], loaded: &[QueryEntry]) {
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+
+    let header = Row::from(vec![
+        "No",
+        "Query Description",
+        "Mean Latency without Ingest",
+        "Mean Latency with Ingest",
+        "Degradation",
+        "Errors/Timeouts with Ingest",
+    ]);
+    table.set_header(header);
+
+    for (i, (e0, e1)) in idle.iter().zip(loaded).enumerate() {
+        let (t0, t1) = (e0.mean_time(), e1.mean_time());
+        table.add_row(vec![
+            Cell::new(i + 1),
+            Cell::new(&e0.desc),
+            Cell::new(format!("{:?}", t0)),
+            Cell::new(format!("{:?}", t1)),
+            Cell::new(format!("{:+.2}%", latency_degradation(t0, t1))),
+            Cell::new(format!("{}/{}", e1.num_errors, e1.num_timeouts)),
+        ]);
+    }
+    println!("{}", table);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{latency_degradation, rate_degradation};
+
+    #[test]
+    fn test_degradation() {
+        let d = latency_degradation(Duration::from_millis(100), Duration::from_millis(150));
+        assert!((d - 50.0).abs() < 1e-9);
+        let d = rate_degradation(1000.0, 800.0);
+        assert!((d - 20.0).abs() < 1e-9);
+    }
+}
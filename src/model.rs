@@ -1,11 +1,12 @@
-use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::format;
 use std::fs::File;
-use std::str::FromStr;
 use std::{fs, path::PathBuf, vec};
 
+use crate::ddl::{parse_schema, TableDef};
 use crate::error::{OidbsError, OidbsResult};
+use crate::queries::{parse_legacy, QueryManifest, QuerySpec};
+use crate::schema::{SchemaSpec, RENDERED_TARGETS, SCHEMA_FILE};
 use chrono::NaiveDateTime;
 use csv::{Writer, WriterBuilder};
 use rand::prelude::SmallRng;
@@ -19,165 +20,291 @@ pub struct Model {
     pub name: String,
     pub target_infos: HashMap<String, TargetInfo>,
     pub has_completed: bool,
+    /// the structured queries from `queries.toml`, which take precedence over `queries/<target>`
+    pub queries: Option<QueryManifest>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TargetInfo {
     pub schema: String,
+    /// the database and table of the first table created in the schema
     pub database: String,
     pub table: String,
     pub query: String,
+    /// all tables created in the schema
+    pub tables: Vec<TableDef>,
 }
 
-#[inline]
-fn get_file_name(path: &PathBuf) -> String {
-    path.file_name().unwrap().to_str().unwrap().to_string()
+impl TargetInfo {
+    /// Parses the tables created in the schema.
+    pub fn new(schema: String, query: String) -> OidbsResult<Self> {
+        let tables = parse_schema(&schema)?;
+        let (database, table) = tables
+            .first()
+            .map(|t| (t.database.clone(), t.name.clone()))
+            .unwrap_or_default();
+        Ok(TargetInfo {
+            schema,
+            database,
+            table,
+            query,
+            tables,
+        })
+    }
+
+    /// the definition of the main table, which the dataset is imported into
+    pub fn table_def(&self) -> Option<&TableDef> {
+        self.tables.first()
+    }
+
+    /// names of the columns of the main table, in the order of the `create table` statement
+    pub fn column_names(&self) -> Vec<String> {
+        self.table_def()
+            .map(|t| t.columns.iter().map(|c| c.name.clone()).collect())
+            .unwrap_or_default()
+    }
 }
 
-#[inline]
-fn get_base_name(path: &PathBuf) -> String {
-    basename(path.to_str().unwrap(), '/').to_string()
+/// the stock models compiled into the binary from `models/` of the source tree, see `build.rs`
+mod bundled {
+    include!(concat!(env!("OUT_DIR"), "/bundled_models.rs"));
 }
 
-fn basename<'a>(path: &'a str, sep: char) -> Cow<'a, str> {
-    let mut pieces = path.rsplit(sep);
-    match pieces.next() {
-        Some(p) => p.into(),
-        None => path.into(),
+/// the origin of the models bundled into the binary
+pub const BUNDLED: &str = "bundled";
+
+/// the files of one model by their paths relative to the model directory,
+/// e.g. `schemas/joinbase`, `queries/joinbase` and `queries.toml`
+pub type ModelFiles = BTreeMap<String, String>;
+
+/// the files of one model before parsing, so a malformed model can be inspected
+#[derive(Debug, Clone)]
+pub struct ModelSource {
+    pub name: String,
+    /// [`BUNDLED`] or the models dir the model is read from
+    pub origin: String,
+    pub files: ModelFiles,
+}
+
+/// the files of `dir` in the model, by their names as the targets
+fn files_in<'a>(files: &'a ModelFiles, dir: &str) -> BTreeMap<String, &'a String> {
+    files
+        .iter()
+        .filter_map(|(p, c)| {
+            let name = p.strip_prefix(dir)?.strip_prefix('/')?;
+            (!name.contains('/')).then(|| (name.to_string(), c))
+        })
+        .collect()
+}
+
+impl ModelSource {
+    fn hand_written_schemas(&self) -> BTreeMap<String, String> {
+        files_in(&self.files, "schemas")
+            .into_iter()
+            .map(|(t, s)| (t, s.clone()))
+            .collect()
+    }
+
+    /// Collects the schemas by targets, a hand-written `schemas/<target>` takes precedence
+    /// over the one rendered from `schema.toml`.
+    pub fn schemas(&self) -> OidbsResult<BTreeMap<String, String>> {
+        let mut rt = self.hand_written_schemas();
+        if let Some(spec) = self.files.get(SCHEMA_FILE) {
+            let spec = SchemaSpec::parse(spec)?;
+            for target in RENDERED_TARGETS {
+                if !rt.contains_key(*target) {
+                    rt.insert(target.to_string(), spec.render(target)?);
+                }
+            }
+        }
+        Ok(rt)
+    }
+
+    /// Finds all problems of the model, which is only usable without any.
+    pub fn problems(&self) -> Vec<String> {
+        let mut rt = vec![];
+        if let Some(toml) = self.files.get("queries.toml") {
+            if let Err(e) = QueryManifest::parse(toml) {
+                rt.push(format!("queries.toml: {}", e));
+            }
+        }
+        let schemas = match self.schemas() {
+            Ok(schemas) => schemas,
+            Err(e) => {
+                rt.push(format!("{}: {}", SCHEMA_FILE, e));
+                self.hand_written_schemas()
+            }
+        };
+        if schemas.is_empty() && !self.files.contains_key(SCHEMA_FILE) {
+            rt.push(format!(
+                "no schemas, which are expected in {} or schemas/<target>",
+                SCHEMA_FILE
+            ));
+        }
+        for (target, schema) in &schemas {
+            match parse_schema(schema) {
+                Err(e) => rt.push(format!("schemas/{}: {}", target, e)),
+                Ok(tables) => match tables.first() {
+                    Some(t) if t.database.is_empty() && target == "joinbase" => {
+                        rt.push(format!(
+                            "schemas/{}: the table should be qualified with the database, e.g. `benchmark.t`",
+                            target
+                        ))
+                    }
+                    Some(t) if t.columns.is_empty() => rt.push(format!(
+                        "schemas/{}: no columns of table {}",
+                        target, t.name
+                    )),
+                    _ => {}
+                },
+            }
+        }
+        for (target, query) in files_in(&self.files, "queries") {
+            if let Err(e) = parse_legacy(query) {
+                rt.push(format!(
+                    "queries/{}: {}, `<desc>: <sql>` expected",
+                    target, e
+                ));
+            }
+        }
+        rt
+    }
+
+    /// Parses the model, the first problem found is returned as the error.
+    pub fn parse(&self) -> OidbsResult<Model> {
+        if let Some(p) = self.problems().into_iter().next() {
+            return Err(OidbsError::InvalidModel(self.name.clone(), p));
+        }
+        const COMPLETED_MODELS: &[&str] = &["pstations"];
+        let mut model = Model {
+            name: self.name.clone(),
+            target_infos: Default::default(),
+            has_completed: COMPLETED_MODELS.contains(&self.name.as_str()),
+            queries: None,
+        };
+        if let Some(toml) = self.files.get("queries.toml") {
+            model.queries = Some(QueryManifest::parse(toml)?);
+        }
+
+        let schema_infos = self.schemas()?;
+        let query_infos = files_in(&self.files, "queries");
+        let mut keys = HashSet::new();
+        keys.extend(schema_infos.keys());
+        keys.extend(query_infos.keys());
+        for target in keys {
+            let schema = schema_infos.get(target).map_or("", |s| s.as_str());
+            let query = query_infos.get(target).map_or("", |q| q.as_str());
+            model.target_infos.insert(
+                target.clone(),
+                TargetInfo::new(schema.into(), query.into())?,
+            );
+        }
+        Ok(model)
     }
 }
 
-fn extract_db_tab(s: &str) -> Option<(String, String)> {
-    if let Some(idx) = s.to_lowercase().find("create table") {
-        let st = idx + "create table".len();
-        let sp = s[st..].find('(').unwrap();
-        // println!("st: {}, ed: {}", st, sp);
-        let t = s[st..st + sp].trim().to_string();
-        let ss: Vec<&str> = t.split('.').collect();
-        if ss.len() == 2 {
-            Some((ss[0].trim().to_string(), ss[1].trim().to_string()))
-        } else {
-            None
+/// Groups the files of the `models/` tree, keyed by `<model>/<path>`, into the models.
+fn sources_of_files<'a, I>(origin: &str, files: I) -> Vec<ModelSource>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut by_model: BTreeMap<&str, ModelFiles> = BTreeMap::new();
+    for (path, content) in files {
+        if let Some((model, rel)) = path.split_once('/') {
+            by_model
+                .entry(model)
+                .or_default()
+                .insert(rel.to_string(), content.to_string());
         }
-    } else {
-        None
     }
+    by_model
+        .into_iter()
+        .map(|(name, files)| ModelSource {
+            name: name.to_string(),
+            origin: origin.to_string(),
+            files,
+        })
+        .collect()
 }
 
-pub fn read_from_path(root_models: String) -> Vec<Model> {
-    let mut rt = vec![];
-    let r = PathBuf::from(root_models);
-    // r.push("schemas");
-    for e in fs::read_dir(r).unwrap() {
-        let path = e.unwrap().path();
-        if path.is_dir() {
-            let name = get_base_name(&path);
-            // log::debug!("{}, name: {:#?}", path.display(), name);
-
-            const COMPLETED_MODELS: &'static [&'static str] = &["pstations"];
-            let has_completed = COMPLETED_MODELS.contains(&name.as_str());
-            let mut model = Model {
-                name: name.clone(),
-                target_infos: Default::default(),
-                has_completed,
-            };
-
-            let mut schema_infos = HashMap::new();
-            let mut query_infos = HashMap::new();
-
-            let mut sp = path.clone();
-            sp.push("schemas");
-            // log::debug!("sp: {:#?}", sp.as_path());
-            assert!(sp.is_dir());
-
-            for f in fs::read_dir(&sp).unwrap() {
-                let path = f.unwrap().path();
-                assert!(path.is_file());
-                let target = get_file_name(&path);
-                // println!("path: {}, target: {:#?}", path.display(), target);
-                let schema = fs::read_to_string(path).unwrap();
-                schema_infos.insert(target, schema);
-            }
+/// the files of the stock models bundled into the binary
+pub fn bundled_sources() -> Vec<ModelSource> {
+    sources_of_files(BUNDLED, bundled::BUNDLED_MODELS.iter().copied())
+}
 
-            let mut qp = path.clone();
-            // log::debug!("qp: {:#?}", qp.as_path());
-            qp.push("queries");
-            assert!(qp.is_dir() || !qp.exists());
-            if qp.exists() {
-                for f in fs::read_dir(&qp).unwrap() {
-                    let path = f.unwrap().path();
-                    assert!(path.is_file());
-                    let target = get_file_name(&path);
-                    // println!("path: {}, target: {:#?}", path.display(), target);
-                    let query = fs::read_to_string(path).unwrap();
-                    query_infos.insert(target, query);
-                }
-            }
+/// Reads the files of the models in the directory, in which every sub directory is a model.
+pub fn read_sources(root_models: &str) -> OidbsResult<Vec<ModelSource>> {
+    let root = PathBuf::from(root_models);
+    if !root.is_dir() {
+        return Err(OidbsError::InvalidArgs(format!(
+            "models dir {}",
+            root_models
+        )));
+    }
+    let mut files = vec![];
+    for e in walkdir::WalkDir::new(&root)
+        .min_depth(2)
+        .sort_by_file_name()
+    {
+        let e = e?;
+        if e.file_type().is_file() {
+            let rel = e.path().strip_prefix(&root).unwrap_or(e.path());
+            let rel: Vec<_> = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            let content =
+                fs::read_to_string(e.path()).map_err(|err| OidbsError::file(e.path(), err))?;
+            files.push((rel.join("/"), content));
+        }
+    }
+    Ok(sources_of_files(
+        root_models,
+        files.iter().map(|(p, c)| (p.as_str(), c.as_str())),
+    ))
+}
 
-            let mut keys = HashSet::new();
-            keys.extend(schema_infos.keys());
-            keys.extend(query_infos.keys());
-
-            for target in keys {
-                let s = schema_infos.get(target);
-                let q = query_infos.get(target);
-                model.target_infos.insert(
-                    target.clone(),
-                    match (s, q) {
-                        (None, None) => unreachable!(),
-                        (None, Some(query)) => TargetInfo {
-                            schema: String::new(),
-                            database: String::new(),
-                            table: String::new(),
-                            query: query.into(),
-                        },
-                        (Some(schema), None) => {
-                            let (database, table) = extract_db_tab(schema).unwrap_or_default();
-                            TargetInfo {
-                                schema: schema.into(),
-                                database,
-                                table,
-                                query: String::new(),
-                            }
-                        }
-                        (Some(schema), Some(query)) => {
-                            let (database, table) = extract_db_tab(schema).unwrap_or_default();
-                            TargetInfo {
-                                schema: schema.into(),
-                                database,
-                                table,
-                                query: query.into(),
-                            }
-                        }
-                    },
-                );
+/// the bundled models, and the ones in `models_dir` on top of them, in which a model
+/// replaces the bundled one of the same name
+pub fn model_sources(models_dir: Option<&str>) -> OidbsResult<Vec<ModelSource>> {
+    let mut sources = bundled_sources();
+    if let Some(dir) = models_dir {
+        for s in read_sources(dir)? {
+            sources.retain(|b| b.name != s.name);
+            sources.push(s);
+        }
+    }
+    Ok(sources)
+}
 
-                // if let Some((database, table)) = extract_db_tab(&schema) {
-                //     model.target_infos.insert(
-                //         target,
-                //         TargetInfo {
-                //             schema,
-                //             database,
-                //             table,
-                //         },
-                //     );
-                // } else {
-                //     //default to benchmark.$(model_name)
-                //     model.target_infos.insert(
-                //         target,
-                //         TargetInfo {
-                //             schema,
-                //             database: "benchmark".into(),
-                //             table: name.clone(),
-                //         },
-                //     );
-                // }
-            }
+/// the stock models bundled into the binary
+pub fn bundled_models() -> OidbsResult<Vec<Model>> {
+    bundled_sources().iter().map(ModelSource::parse).collect()
+}
 
-            rt.push(model);
+/// Reads the models from the directory, in which every sub directory is a model.
+pub fn read_from_path(root_models: String) -> OidbsResult<Vec<Model>> {
+    read_sources(&root_models)?
+        .iter()
+        .map(ModelSource::parse)
+        .collect()
+}
+
+/// Loads the bundled models, and the ones in `models_dir` on top of them. A malformed model
+/// is skipped with a warning, so it does not take down the other models,
+/// see `oidbs models validate`.
+pub fn load_models(models_dir: Option<&str>) -> OidbsResult<Vec<Model>> {
+    let mut models = vec![];
+    for s in model_sources(models_dir)? {
+        match s.parse() {
+            Ok(m) => {
+                log::debug!("model {} loaded from {}", m.name, s.origin);
+                models.push(m);
+            }
+            Err(e) => log::warn!("model {} from {} skipped: {}", s.name, s.origin, e),
         }
     }
-    rt
+    Ok(models)
 }
 
 pub enum GenWriter<'a> {
@@ -219,27 +346,9 @@ impl PStations {
         ts: NaiveDateTime,
         rng: &mut SmallRng,
         model_paras: &Map<String, Value>,
-    ) -> Vec<PStations> {
-        let num_stations = if let Some(v) = model_paras.get("num_stations") {
-            log::trace!("v:{}", v);
-            let ret = v
-                .as_i64()
-                .expect("num_stations should be a postivie integer");
-            assert!(ret > 0);
-            ret as _
-        } else {
-            5_000u32
-        };
-        let num_sensors = if let Some(v) = model_paras.get("num_sensors") {
-            log::trace!("v:{}", v);
-            let ret = v
-                .as_i64()
-                .expect("num_stations should be a postivie integer");
-            assert!(ret > 0);
-            ret as _
-        } else {
-            200u8
-        };
+    ) -> OidbsResult<Vec<PStations>> {
+        let num_stations: u32 = positive_parameter(model_paras, "num_stations", 5_000)?;
+        let num_sensors: u8 = positive_parameter(model_paras, "num_sensors", 200)?;
         let mut rt = Vec::with_capacity(num_stations as usize * num_sensors as usize);
         for station_id in 0..num_stations {
             for sensor_id in 0..num_sensors {
@@ -257,7 +366,30 @@ impl PStations {
                 })
             }
         }
-        rt
+        Ok(rt)
+    }
+}
+
+/// the positive integer model parameter `name`, `default` if not given
+fn positive_parameter<T: TryFrom<i64>>(
+    model_paras: &Map<String, Value>,
+    name: &str,
+    default: T,
+) -> OidbsResult<T> {
+    match model_paras.get(name) {
+        Some(v) => {
+            log::trace!("{}: {}", name, v);
+            v.as_i64()
+                .filter(|n| *n > 0)
+                .and_then(|n| T::try_from(n).ok())
+                .ok_or_else(|| {
+                    OidbsError::InvalidModelParameter(
+                        name.into(),
+                        format!("{} is not a positive integer in range", v),
+                    )
+                })
+        }
+        None => Ok(default),
     }
 }
 
@@ -283,7 +415,7 @@ impl GenRecords for PStations {
         model_paras: &Map<String, Value>,
     ) -> OidbsResult<Vec<String>> {
         log::trace!("model_paras: {:?}", model_paras);
-        let pss = PStations::gen_records(ts, rng, model_paras);
+        let pss = PStations::gen_records(ts, rng, model_paras)?;
         let rt = pss
             .iter()
             .map(|ps| {
@@ -301,7 +433,7 @@ impl GenRecords for PStations {
         rng: &mut SmallRng,
         model_paras: &Map<String, Value>,
     ) -> OidbsResult<Records> {
-        let pss = PStations::gen_records(ts, rng, model_paras);
+        let pss = PStations::gen_records(ts, rng, model_paras)?;
         let nlines = pss.len() as u64;
         let mut wtr = vec![];
         for ps in pss {
@@ -343,15 +475,48 @@ impl Model {
     //     Ok(())
     // }
 
+    /// the queries to run against the target, from `queries.toml` or the legacy `queries/<target>`
+    pub fn queries_for(&self, target: &str) -> OidbsResult<Vec<QuerySpec>> {
+        match &self.queries {
+            Some(manifest) => Ok(manifest.for_target(target)),
+            None => match self.target_infos.get(target) {
+                Some(info) if !info.query.is_empty() => parse_legacy(&info.query),
+                _ => Err(OidbsError::UnimplementedModel(format!(
+                    "{} for target {}",
+                    self.name, target
+                ))),
+            },
+        }
+    }
+
+    /// the query of the `concurrency` measurement mode against the target, from `queries.toml`
+    pub fn concurrency_query_for(&self, target: &str) -> OidbsResult<String> {
+        self.queries
+            .as_ref()
+            .and_then(|m| m.concurrency_for(target))
+            .ok_or_else(|| {
+                OidbsError::UnimplementedModel(format!(
+                    "{} for the concurrency mode of target {}",
+                    self.name, target
+                ))
+            })
+    }
+
+    /// ids of the queries in `queries.toml` which are neither defined nor skipped for the target
+    pub fn missing_queries(&self, target: &str) -> Vec<&str> {
+        self.queries
+            .as_ref()
+            .map(|m| m.missing_for(target))
+            .unwrap_or_default()
+    }
+
     pub fn ensure_gen_dir_clean(&self, path: &str) -> OidbsResult<()> {
         let mut output = PathBuf::from(path);
         output.push(&self.name);
         if output.exists() {
-            fs::remove_dir_all(output.as_path())?;
-            fs::create_dir(output)?;
-        } else {
-            fs::create_dir(output)?;
+            fs::remove_dir_all(&output).map_err(|e| OidbsError::file(&output, e))?;
         }
+        fs::create_dir(&output).map_err(|e| OidbsError::file(&output, e))?;
 
         Ok(())
     }
@@ -368,35 +533,6 @@ impl Model {
     // }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum TargetKind {
-    JoinBase,
-    TimeScale,
-    All,
-}
-
-impl TargetKind {
-    pub fn to_str(&self) -> &'static str {
-        match self {
-            TargetKind::JoinBase => "joinbase",
-            TargetKind::TimeScale => "timescale",
-            TargetKind::All => "all",
-        }
-    }
-}
-
-impl FromStr for TargetKind {
-    type Err = OidbsError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "joinbase" => Ok(TargetKind::JoinBase),
-            "timescale" => Ok(TargetKind::TimeScale),
-            "all" => Ok(TargetKind::All),
-            _ => Err(OidbsError::InvalidArgs(s.into())),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use std::{
@@ -418,8 +554,8 @@ mod tests {
         let mut rng: SmallRng = SmallRng::seed_from_u64(666666);
         let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         root.push("models");
-        let models = read_from_path(root.display().to_string());
-        // println!("{:#?}", models);
+        let models = read_from_path(root.display().to_string()).unwrap();
+        assert_eq!(models, super::bundled_models().unwrap());
 
         let output_path = "/tmp/test";
         for m in models {
@@ -457,6 +593,7 @@ mod tests {
             name: "pstations".into(),
             target_infos: Default::default(),
             has_completed: Default::default(),
+            queries: None,
         };
         let f = OpenOptions::new()
             .read(true)
@@ -473,16 +610,58 @@ mod tests {
     }
 
     #[test]
-    fn test_basename() {
-        let bn = crate::model::basename("/a/b/c_d", '/');
-        println!("bn: {}", bn);
-        assert_eq!(bn, std::borrow::Cow::from("c_d"));
+    fn test_model_problems() {
+        for s in super::bundled_sources() {
+            assert!(s.problems().is_empty(), "{}: {:?}", s.name, s.problems());
+        }
+        let source = |files: &[(&str, &str)]| super::ModelSource {
+            name: "m".into(),
+            origin: "test".into(),
+            files: files
+                .iter()
+                .map(|(p, c)| (p.to_string(), c.to_string()))
+                .collect(),
+        };
+        let s = source(&[
+            ("schemas/joinbase", "create table db.t (a int)"),
+            (
+                "schemas/timescale",
+                "CREATE TABLE IF NOT EXISTS \"t\"(a int);",
+            ),
+            ("queries/joinbase", "count: select count(*) from db.t"),
+        ]);
+        assert!(s.problems().is_empty());
+        let m = s.parse().unwrap();
+        assert_eq!(m.target_infos["timescale"].table, "t");
+        assert_eq!(m.target_infos["timescale"].database, "");
+
+        let s = source(&[("queries/joinbase", "count: select count(*) from db.t")]);
+        assert_eq!(s.problems().len(), 1);
+        assert!(s.parse().is_err());
+        let s = source(&[
+            ("schemas/joinbase", "create table t(a int)"),
+            ("schemas/timescale", "create table (a int)"),
+            ("queries/joinbase", "select count(*) from db.t"),
+        ]);
+        let problems = s.problems();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("schemas/joinbase"));
+        assert!(problems[1].starts_with("schemas/timescale"));
+        assert!(problems[2].starts_with("queries/joinbase"));
     }
 
     #[test]
-    fn test_extract_db_tab() {
-        let db_tab = crate::model::extract_db_tab("create table a123.b456\n");
-        println!("db_tab: {:#?}", db_tab);
-        assert_eq!(db_tab, Some(("a123".to_string(), "b456".to_string())));
+    fn test_column_names() {
+        let info = super::TargetInfo::new(
+            "drop table if exists t;\nCREATE TABLE \"t\"(\n    a Int8,\n    b Decimal(10, 2) NOT NULL,\n    ts DateTime,\n    PRIMARY KEY (a)\n)\nPARTITION BY ymdh(ts);".into(),
+            String::new(),
+        )
+        .unwrap();
+        assert_eq!(info.column_names(), ["a", "b", "ts"]);
+        assert_eq!(info.table, "t");
+        assert_eq!(
+            info.table_def().unwrap().column("b").unwrap().data_type,
+            "Decimal(10, 2)"
+        );
     }
 }
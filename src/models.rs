@@ -0,0 +1,199 @@
+//! `oidbs models`, to list, inspect and validate the loaded models.
+
+use crate::{
+    error::OidbsError,
+    model::{Model, ModelSource},
+    schema::SCHEMA_FILE,
+};
+use clap::{Args, Subcommand};
+use comfy_table::{Row, Table};
+
+#[derive(Args, Debug)]
+pub struct Models {
+    #[clap(subcommand)]
+    pub(crate) command: ModelsCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ModelsCommand {
+    /// List all models with their origins, targets, queries and generator support
+    List,
+    /// Show the targets of the model with their parsed database/table, schemas and query coverage
+    Show {
+        /// the model name
+        name: String,
+    },
+    /// Validate all models or the given one, exits with an error if any problem is found
+    Validate {
+        /// the model name, all models if not given
+        name: Option<String>,
+    },
+}
+
+pub struct ModelInspector {
+    command: ModelsCommand,
+    sources: Vec<ModelSource>,
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn sorted_targets(model: &Model) -> Vec<&String> {
+    let mut targets: Vec<_> = model.target_infos.keys().collect();
+    targets.sort();
+    targets
+}
+
+impl ModelInspector {
+    pub fn new(models: Models, sources: Vec<ModelSource>) -> Self {
+        Self {
+            command: models.command,
+            sources,
+        }
+    }
+
+    pub fn run(self) -> Result<(), OidbsError> {
+        match &self.command {
+            ModelsCommand::List => {
+                self.list();
+                Ok(())
+            }
+            ModelsCommand::Show { name } => self.show(self.find(name)?),
+            ModelsCommand::Validate { name } => match name {
+                Some(name) => self.validate(&[self.find(name)?]),
+                None => self.validate(&self.sources.iter().collect::<Vec<_>>()),
+            },
+        }
+    }
+
+    fn find(&self, name: &str) -> Result<&ModelSource, OidbsError> {
+        self.sources
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| OidbsError::UnimplementedModel(name.to_string()))
+    }
+
+    fn list(&self) {
+        let mut table = Table::new();
+        table.load_preset("||--+-++|    ++++++");
+        table.set_header(Row::from(vec![
+            "Model", "Origin", "Targets", "Queries", "Gen", "Status",
+        ]));
+        for s in &self.sources {
+            let row = match s.parse() {
+                Ok(m) => {
+                    let queries = match &m.queries {
+                        Some(q) => q.queries.len().to_string(),
+                        None if m.target_infos.values().any(|i| !i.query.is_empty()) => {
+                            "legacy".to_string()
+                        }
+                        None => "none".to_string(),
+                    };
+                    let targets = sorted_targets(&m)
+                        .iter()
+                        .map(|t| t.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    vec![
+                        m.name.clone(),
+                        s.origin.clone(),
+                        targets,
+                        queries,
+                        yes_no(m.has_completed).to_string(),
+                        "ok".to_string(),
+                    ]
+                }
+                Err(_) => vec![
+                    s.name.clone(),
+                    s.origin.clone(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    format!("{} problem(s)", s.problems().len()),
+                ],
+            };
+            table.add_row(row);
+        }
+        println!("{table}");
+    }
+
+    fn show(&self, source: &ModelSource) -> Result<(), OidbsError> {
+        let model = source.parse()?;
+        println!("model: {}", model.name);
+        println!("origin: {}", source.origin);
+        println!("generator support: {}", yes_no(model.has_completed));
+        println!(
+            "queries: {}",
+            model.queries.as_ref().map_or(
+                "legacy queries/<target> files".to_string(),
+                |q| format!("{} in queries.toml", q.queries.len())
+            )
+        );
+        for target in sorted_targets(&model) {
+            let info = &model.target_infos[target];
+            println!("\n[{}]", target);
+            if !info.table.is_empty() {
+                if info.database.is_empty() {
+                    println!("table: {}", info.table);
+                } else {
+                    println!("table: {}.{}", info.database, info.table);
+                }
+            }
+            if let Some(p) = info.table_def().and_then(|t| t.partitioning.as_ref()) {
+                println!("partitioning: {}({})", p.method, p.args.join(", "));
+            }
+            match model.queries_for(target) {
+                Ok(qs) => println!(
+                    "queries: {}",
+                    qs.iter()
+                        .map(|q| q.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Err(_) => println!("queries: none"),
+            }
+            let missing = model.missing_queries(target);
+            if !missing.is_empty() {
+                println!("missing queries: {}", missing.join(", "));
+            }
+            if !info.schema.is_empty() {
+                let hand_written = format!("schemas/{}", target);
+                if source.files.contains_key(&hand_written) {
+                    println!("schema({}):\n{}", hand_written, info.schema.trim_end());
+                } else {
+                    println!(
+                        "schema(rendered from {}):\n{}",
+                        SCHEMA_FILE,
+                        info.schema.trim_end()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self, sources: &[&ModelSource]) -> Result<(), OidbsError> {
+        let mut num_problems = 0;
+        for s in sources {
+            let problems = s.problems();
+            if problems.is_empty() {
+                println!("model {}({}): ok", s.name, s.origin);
+            } else {
+                println!("model {}({}):", s.name, s.origin);
+                for p in &problems {
+                    println!("  {}", p);
+                }
+            }
+            num_problems += problems.len();
+        }
+        match num_problems {
+            0 => Ok(()),
+            n => Err(OidbsError::ModelProblems(n)),
+        }
+    }
+}
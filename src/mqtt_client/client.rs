@@ -6,7 +6,7 @@ use mqttbytes::{
 };
 use std::{
     io::{self, Read, Write},
-    net::{SocketAddr, TcpStream},
+    net::{TcpStream, ToSocketAddrs},
     time::Duration,
 };
 
@@ -87,7 +87,7 @@ impl Network {
             return Ok(());
         }
 
-        self.socket.write(&write[..])?;
+        self.socket.write_all(&write[..])?;
         write.clear();
         Ok(())
     }
@@ -107,8 +107,15 @@ const MAX_PACKET_SIZE: usize = 1024 * 1024;
 #[allow(dead_code)]
 impl Client {
     pub fn new(options: MqttOptions) -> Result<Self, io::Error> {
-        let s = format!("{}:{}", options.broker_addr, options.port);
-        let socket_addr: SocketAddr = s.parse().unwrap();
+        let socket_addr = (options.broker_addr.as_str(), options.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    format!("no address of {}", options.broker_addr),
+                )
+            })?;
         let socket =
             TcpStream::connect_timeout(&socket_addr, Duration::from_secs(options.conn_timeout))?;
         let network = Network::new(socket, MAX_PACKET_SIZE);
@@ -157,7 +164,7 @@ impl Client {
         qos: QoS,
         // retain: bool,
         payload: Bytes,
-    ) -> Result<(), mqttbytes::Error>
+    ) -> Result<(), Error>
     where
         S: Into<String>,
     {
@@ -167,7 +174,7 @@ impl Client {
         // publish.retain = retain;
         // publish.pkid = self.next_pkid();
         publish.write(&mut buf)?;
-        self.network.write(&mut buf).unwrap();
+        self.network.write(&mut buf)?;
 
         Ok(())
     }
@@ -113,13 +113,17 @@ pub struct MqttOptions {
 #[allow(dead_code)]
 impl MqttOptions {
     /// New mqtt options
-    pub fn new<S: Into<String>, T: Into<String>>(id: S, host: T, port: u16) -> MqttOptions {
+    pub fn new<S: Into<String>, T: Into<String>>(
+        id: S,
+        host: T,
+        port: u16,
+    ) -> Result<MqttOptions, Error> {
         let id = id.into();
         if id.starts_with(' ') || id.is_empty() {
-            panic!("Invalid client id")
+            return Err(Error::InvalidOptions("invalid client id"));
         }
 
-        MqttOptions {
+        Ok(MqttOptions {
             broker_addr: host.into(),
             port,
             transport: Transport::tcp(),
@@ -135,7 +139,7 @@ impl MqttOptions {
             inflight: 100,
             last_will: None,
             conn_timeout: 5,
-        }
+        })
     }
 
     /// Broker address
@@ -163,13 +167,13 @@ impl MqttOptions {
 
     /// Set number of seconds after which client should ping the broker
     /// if there is no other data exchange
-    pub fn set_keep_alive(&mut self, duration: Duration) -> &mut Self {
+    pub fn set_keep_alive(&mut self, duration: Duration) -> Result<&mut Self, Error> {
         if duration.as_secs() < 5 {
-            panic!("Keep alives should be >= 5  secs");
+            return Err(Error::InvalidOptions("keep alives should be >= 5 secs"));
         }
 
         self.keep_alive = duration;
-        self
+        Ok(self)
     }
 
     /// Keep alive time
@@ -250,13 +254,13 @@ impl MqttOptions {
     }
 
     /// Set number of concurrent in flight messages
-    pub fn set_inflight(&mut self, inflight: u16) -> &mut Self {
+    pub fn set_inflight(&mut self, inflight: u16) -> Result<&mut Self, Error> {
         if inflight == 0 {
-            panic!("zero in flight is not allowed")
+            return Err(Error::InvalidOptions("zero in flight is not allowed"));
         }
 
         self.inflight = inflight;
-        self
+        Ok(self)
     }
 
     /// Number of concurrent in flight messages
@@ -485,6 +489,9 @@ pub enum Error {
     CollisionTimeout,
     #[error("Mqtt serialization/deserialization error")]
     Deserialization(mqttbytes::Error),
+    /// Invalid options to connect with
+    #[error("Invalid options, {0}")]
+    InvalidOptions(&'static str),
 }
 
 impl From<mqttbytes::Error> for Error {
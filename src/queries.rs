@@ -0,0 +1,258 @@
+//! The query manifest of a model, `queries.toml` in the model directory.
+//!
+//! ```toml
+//! [[queries]]
+//! id = "rides_per_rate_code"
+//! desc = "How many rides of each rate type took place in the month?"
+//! tags = ["group-by", "time-filter"]
+//! sql = """
+//! SELECT rate_code, COUNT(vendor_id) num_trips FROM nyct_lite
+//! WHERE pickup_datetime < dt'2016-02-01 00:00:00' GROUP BY rate_code ORDER BY rate_code
+//! """
+//! expected_rows = 6
+//!
+//! [queries.targets.timescale]
+//! sql = "SELECT rate_code, ... WHERE pickup_datetime < '2016-02-01' GROUP BY rate_code ORDER BY rate_code"
+//! ```
+//!
+//! The `sql` of a query is shared by all targets, unless it is overridden in `targets.<name>`.
+//! A target can skip a query with `skip = true`.
+//!
+//! The query of the `concurrency` measurement mode is given in its own table,
+//! with the same per-target variants:
+//!
+//! ```toml
+//! [concurrency]
+//! sql = "select count(total_amount) from nyct_lite where {{rand_time_range:12h}} and total_amount<0"
+//! ```
+
+use crate::error::{OidbsError, OidbsResult};
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryManifest {
+    #[serde(default)]
+    pub queries: Vec<QueryDef>,
+    /// the query run by all threads in the `concurrency` measurement mode
+    pub concurrency: Option<ConcurrencyDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryDef {
+    /// the stable id, which identifies the query across runs and targets
+    pub id: String,
+    pub desc: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// the SQL shared by all targets
+    pub sql: Option<String>,
+    /// the expected number of returned rows, a different one is counted as an error
+    pub expected_rows: Option<usize>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, TargetQuery>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyDef {
+    pub sql: Option<String>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, TargetQuery>,
+}
+
+/// the per-target variant of a query
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetQuery {
+    pub sql: Option<String>,
+    pub expected_rows: Option<usize>,
+    #[serde(default)]
+    pub skip: bool,
+}
+
+/// a query resolved for one target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuerySpec {
+    pub id: String,
+    pub desc: String,
+    pub sql: String,
+    pub expected_rows: Option<usize>,
+}
+
+impl QueryManifest {
+    pub fn parse(s: &str) -> OidbsResult<Self> {
+        let manifest: QueryManifest = toml::from_str(s)
+            .map_err(|e| OidbsError::InvalidArgs(format!("query manifest({})", e)))?;
+        let mut ids = HashSet::new();
+        for q in &manifest.queries {
+            if q.id.is_empty() || !ids.insert(q.id.as_str()) {
+                return Err(OidbsError::InvalidArgs(format!(
+                    "query manifest(empty or duplicated id '{}')",
+                    q.id
+                )));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// the queries for the target in the manifest order, skipped and missing ones are excluded
+    pub fn for_target(&self, target: &str) -> Vec<QuerySpec> {
+        self.queries
+            .iter()
+            .filter_map(|q| {
+                let tq = q.targets.get(target).cloned().unwrap_or_default();
+                if tq.skip {
+                    return None;
+                }
+                Some(QuerySpec {
+                    id: q.id.clone(),
+                    desc: q.desc.clone(),
+                    sql: tq.sql.or_else(|| q.sql.clone())?.trim().to_string(),
+                    expected_rows: tq.expected_rows.or(q.expected_rows),
+                })
+            })
+            .collect()
+    }
+
+    /// the query of the `concurrency` measurement mode for the target, if any
+    pub fn concurrency_for(&self, target: &str) -> Option<String> {
+        let c = self.concurrency.as_ref()?;
+        let tq = c.targets.get(target).cloned().unwrap_or_default();
+        if tq.skip {
+            return None;
+        }
+        Some(tq.sql.or_else(|| c.sql.clone())?.trim().to_string())
+    }
+
+    /// ids of the queries which have no SQL for the target and are not skipped by it
+    pub fn missing_for(&self, target: &str) -> Vec<&str> {
+        self.queries
+            .iter()
+            .filter(|q| {
+                let tq = q.targets.get(target);
+                q.sql.is_none()
+                    && !tq.is_some_and(|t| t.skip)
+                    && tq.and_then(|t| t.sql.as_ref()).is_none()
+            })
+            .map(|q| q.id.as_str())
+            .collect()
+    }
+}
+
+/// Parses the legacy `queries/<target>` file, in which every line is `<desc>: <sql>`.
+/// The queries are identified by their positions, as `q1`, `q2`...
+pub fn parse_legacy(query: &str) -> OidbsResult<Vec<QuerySpec>> {
+    query
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            let idx = line
+                .find(':')
+                .ok_or_else(|| OidbsError::InvalidArgs(format!("query line '{}'", line)))?;
+            Ok(QuerySpec {
+                id: format!("q{}", i + 1),
+                desc: line[..idx].trim().to_string(),
+                sql: line[idx + 1..].trim().to_string(),
+                expected_rows: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_legacy, QueryManifest};
+    use crate::template::QueryTemplate;
+
+    #[test]
+    fn test_query_manifest() {
+        let m = QueryManifest::parse(
+            r#"
+[[queries]]
+id = "count"
+desc = "row counts: of whole dataset"
+tags = ["scan"]
+sql = "select count(*) from t"
+expected_rows = 1
+
+[[queries]]
+id = "by_day"
+desc = "daily stats"
+sql = """
+select date(ts), avg(v)
+from t group by date(ts)
+"""
+[queries.targets.timescale]
+sql = "select time_bucket('1 day', ts), avg(v) from t group by 1"
+[queries.targets.other]
+skip = true
+
+[[queries]]
+id = "jb_only"
+desc = "only for joinbase"
+[queries.targets.joinbase]
+sql = "select 1 from t where parts 2016010100"
+
+[concurrency]
+sql = "select count(v) from t "
+[concurrency.targets.other]
+skip = true
+"#,
+        )
+        .unwrap();
+        let jb = m.for_target("joinbase");
+        assert_eq!(
+            jb.iter().map(|q| q.id.as_str()).collect::<Vec<_>>(),
+            ["count", "by_day", "jb_only"]
+        );
+        assert_eq!(jb[0].desc, "row counts: of whole dataset");
+        assert_eq!(jb[0].expected_rows, Some(1));
+        assert_eq!(jb[1].sql, "select date(ts), avg(v)\nfrom t group by date(ts)");
+        let ts = m.for_target("timescale");
+        assert_eq!(ts.len(), 2);
+        assert!(ts[1].sql.starts_with("select time_bucket"));
+        assert_eq!(m.missing_for("timescale"), ["jb_only"]);
+        assert_eq!(m.for_target("other").len(), 1);
+        assert_eq!(m.missing_for("other"), ["jb_only"]);
+        assert!(m.missing_for("joinbase").is_empty());
+        assert_eq!(
+            m.concurrency_for("timescale").as_deref(),
+            Some("select count(v) from t")
+        );
+        assert_eq!(m.concurrency_for("other"), None);
+
+        assert!(QueryManifest::parse("[[queries]]\nid = \"a\"\ndesc = \"\"\n[[queries]]\nid = \"a\"\ndesc = \"\"").is_err());
+        assert!(QueryManifest::parse("[[queries]]\nid = \"a\"\ndescription = \"\"").is_err());
+    }
+
+    #[test]
+    fn test_bundled_manifests() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("models");
+        for e in std::fs::read_dir(root).unwrap() {
+            let path = e.unwrap().path().join("queries.toml");
+            if path.is_file() {
+                let m = QueryManifest::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+                assert!(m.missing_for("joinbase").is_empty(), "{}", path.display());
+                assert!(m.missing_for("timescale").is_empty(), "{}", path.display());
+                assert!(m.concurrency_for("joinbase").is_some(), "{}", path.display());
+                assert!(m.concurrency_for("timescale").is_some(), "{}", path.display());
+                // the concurrent queries draw different data, not the result caches
+                let sql = m.concurrency_for("joinbase").unwrap();
+                assert!(!QueryTemplate::parse(&sql).unwrap().is_fixed(), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_legacy() {
+        let qs = parse_legacy("count: select count(*) from t\n\nsum: select sum(v) from t\n").unwrap();
+        assert_eq!(qs.len(), 2);
+        assert_eq!(qs[1].id, "q2");
+        assert_eq!(qs[1].sql, "select sum(v) from t");
+        assert!(parse_legacy("no description").is_err());
+    }
+}
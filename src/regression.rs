@@ -0,0 +1,141 @@
+/// two-sided critical values of Student's t distribution at 95% confidence, indexed by `df - 1`
+const T_CRITICAL_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+fn t_critical_95(df: f64) -> f64 {
+    // floor the degrees of freedom, which is conservative
+    match df.floor() as usize {
+        0 => f64::INFINITY,
+        df @ 1..=30 => T_CRITICAL_95[df - 1],
+        31..=60 => 2.000,
+        61..=120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+fn mean_var(xs: &[f64]) -> (f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let var = if xs.len() > 1 {
+        xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+    } else {
+        0.0
+    };
+    (mean, var)
+}
+
+/// Welch's t-test on the means of two samples at 95% confidence.
+/// Returns `None` if any sample has less than 2 values, in which case the noise can not be estimated.
+pub(crate) fn is_significant(a: &[f64], b: &[f64]) -> Option<bool> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (ma, va) = mean_var(a);
+    let (mb, vb) = mean_var(b);
+    let (sa, sb) = (va / a.len() as f64, vb / b.len() as f64);
+    let se = (sa + sb).sqrt();
+    if se == 0.0 {
+        return Some(ma != mb);
+    }
+    let t = (ma - mb).abs() / se;
+    // Welch–Satterthwaite equation
+    let df = (sa + sb).powi(2)
+        / (sa.powi(2) / (a.len() as f64 - 1.0) + sb.powi(2) / (b.len() as f64 - 1.0));
+    Some(t > t_critical_95(df))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Regression,
+    Improvement,
+    Unchanged,
+}
+
+impl Verdict {
+    pub(crate) fn to_str(self) -> &'static str {
+        match self {
+            Verdict::Regression => "REGRESSION",
+            Verdict::Improvement => "improvement",
+            Verdict::Unchanged => "-",
+        }
+    }
+}
+
+/// the comparison of latency samples of one query between the baseline and the current run
+#[derive(Debug, Clone)]
+pub(crate) struct Comparison {
+    pub(crate) baseline_mean: f64,
+    pub(crate) current_mean: f64,
+    /// the relative change of the mean, positive means slower
+    pub(crate) change: f64,
+    pub(crate) verdict: Verdict,
+}
+
+/// Compares latency samples, a difference is marked as a regression or an improvement
+/// only if the relative change of the mean is beyond `threshold` and it is statistically significant.
+/// When the noise can not be estimated from samples, the threshold alone decides.
+pub(crate) fn compare_latencies(
+    baseline: &[f64],
+    current: &[f64],
+    threshold: f64,
+) -> Option<Comparison> {
+    if baseline.is_empty() || current.is_empty() {
+        return None;
+    }
+    let (baseline_mean, _) = mean_var(baseline);
+    let (current_mean, _) = mean_var(current);
+    let change = current_mean / baseline_mean - 1.0;
+    let significant = is_significant(baseline, current).unwrap_or(true);
+    let verdict = if significant && change > threshold {
+        Verdict::Regression
+    } else if significant && change < -threshold {
+        Verdict::Improvement
+    } else {
+        Verdict::Unchanged
+    };
+    Some(Comparison {
+        baseline_mean,
+        current_mean,
+        change,
+        verdict,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_latencies, is_significant, Verdict};
+
+    #[test]
+    fn test_welch() {
+        let a = [100.0, 102.0, 98.0, 101.0, 99.0];
+        let b = [150.0, 149.0, 152.0, 151.0, 148.0];
+        assert_eq!(is_significant(&a, &b), Some(true));
+        let noisy = [60.0, 140.0, 90.0, 130.0, 80.0];
+        assert_eq!(is_significant(&a, &noisy), Some(false));
+        assert_eq!(is_significant(&a, &[100.0]), None);
+    }
+
+    #[test]
+    fn test_compare_latencies() {
+        let base = [100.0, 102.0, 98.0, 101.0, 99.0];
+        let slow = [150.0, 149.0, 152.0, 151.0, 148.0];
+        let fast = [50.0, 51.0, 49.0, 50.0, 50.0];
+        let c = compare_latencies(&base, &slow, 0.1).unwrap();
+        assert_eq!(c.verdict, Verdict::Regression);
+        assert!((c.change - 0.5).abs() < 1e-9);
+        assert_eq!(
+            compare_latencies(&base, &fast, 0.1).unwrap().verdict,
+            Verdict::Improvement
+        );
+        // significant but within the threshold
+        let bit_slow = [105.0, 107.0, 103.0, 106.0, 104.0];
+        assert_eq!(
+            compare_latencies(&base, &bit_slow, 0.1).unwrap().verdict,
+            Verdict::Unchanged
+        );
+        assert!(compare_latencies(&[], &slow, 0.1).is_none());
+    }
+}
@@ -0,0 +1,571 @@
+use crate::{bench::uppercase_first_letter, error::OidbsError, results::RunResult};
+use clap::Args;
+use std::{fmt::Write, fs, path::PathBuf};
+
+#[derive(Args, Debug)]
+pub struct Report {
+    /// result files to render, which are JSON result documents written by `oidbs bench -o`, or the legacy `latency_results.csv`/`concurrency_results.csv`
+    #[clap(required = true)]
+    inputs: Vec<String>,
+
+    /// output directory for the rendered charts
+    #[clap(short, long, default_value_t = String::from("."))]
+    output_dir: String,
+
+    /// format of output, options: svg(one file per chart), html(one self-contained file for all charts)
+    #[clap(short, long, default_value_t = String::from("svg"))]
+    format: String,
+
+    /// the series(e.g. JoinBase) which ratios are computed against, default to the first series
+    #[clap(short, long)]
+    baseline: Option<String>,
+}
+
+/// the results of one database, merged from all input files
+#[derive(Debug, Default, PartialEq)]
+struct Series {
+    label: String,
+    /// the best latency of every query, in microseconds
+    latencies_us: Vec<Option<f64>>,
+    qps: Option<f64>,
+    /// the QPS at every number of threads of the concurrency sweep
+    sweep: Vec<(usize, f64)>,
+}
+
+pub struct Reporter {
+    series: Vec<Series>,
+    output_dir: PathBuf,
+    is_html: bool,
+    baseline: Option<String>,
+}
+
+impl Reporter {
+    pub fn new(report: Report) -> Result<Self, OidbsError> {
+        let is_html = match report.format.as_str() {
+            "svg" => false,
+            "html" => true,
+            _ => return Err(OidbsError::InvalidArgs("format".into())),
+        };
+        let mut series: Vec<Series> = vec![];
+        for input in &report.inputs {
+            let loaded = if input.ends_with(".csv") {
+                load_csv(&fs::read_to_string(input)?)?
+            } else {
+                vec![series_of_run(&RunResult::read_from(input)?)]
+            };
+            for s in loaded {
+                merge_series(&mut series, s);
+            }
+        }
+        Ok(Self {
+            series,
+            output_dir: PathBuf::from(report.output_dir),
+            is_html,
+            baseline: report.baseline,
+        })
+    }
+
+    pub fn run(self) -> Result<(), OidbsError> {
+        let charts = self.build_charts()?;
+        if charts.is_empty() {
+            return Err(OidbsError::Generic("no chartable results found in inputs"));
+        }
+        fs::create_dir_all(&self.output_dir)?;
+        if self.is_html {
+            let mut html = String::from(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>OIDBS Benchmark Report</title>\n</head>\n<body style=\"font-family:sans-serif\">\n<h1>OIDBS Benchmark Report</h1>\n",
+            );
+            for (_, chart) in &charts {
+                html.push_str(&chart.render_svg());
+                html.push('\n');
+            }
+            html.push_str("</body>\n</html>\n");
+            let path = self.output_dir.join("oidbs_bench_report.html");
+            fs::write(&path, html)?;
+            println!("report written to {}", path.display());
+        } else {
+            for (name, chart) in &charts {
+                let path = self.output_dir.join(format!("oidbs_bench_{}.svg", name));
+                fs::write(&path, chart.render_svg())?;
+                println!("chart written to {}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn build_charts(&self) -> Result<Vec<(&'static str, BarChart)>, OidbsError> {
+        let mut charts = vec![];
+        let with_latencies: Vec<&Series> = self
+            .series
+            .iter()
+            .filter(|s| !s.latencies_us.is_empty())
+            .collect();
+        let num_queries = with_latencies
+            .iter()
+            .map(|s| s.latencies_us.len())
+            .max()
+            .unwrap_or(0);
+        let query_labels: Vec<String> = (1..=num_queries).map(|i| format!("Q{}", i)).collect();
+
+        if !with_latencies.is_empty() {
+            charts.push((
+                "latency_results",
+                BarChart {
+                    title: "OIDBS Benchmark - Query Latency".into(),
+                    y_label: "End-to-end Query Time (log scale) (microseconds)".into(),
+                    categories: query_labels.clone(),
+                    series: with_latencies
+                        .iter()
+                        .map(|s| (s.label.clone(), s.latencies_us.clone()))
+                        .collect(),
+                    log_scale: true,
+                    reference: None,
+                },
+            ));
+        }
+
+        let with_qps: Vec<&Series> = self.series.iter().filter(|s| s.qps.is_some()).collect();
+        if !with_qps.is_empty() {
+            charts.push((
+                "concurrency_results",
+                BarChart {
+                    title: "OIDBS Benchmark - Query Concurrency".into(),
+                    y_label: "Number of Queries Per Second (log scale)".into(),
+                    categories: vec!["QPS".into()],
+                    series: with_qps
+                        .iter()
+                        .map(|s| (s.label.clone(), vec![s.qps]))
+                        .collect(),
+                    log_scale: true,
+                    reference: None,
+                },
+            ));
+        }
+
+        let with_sweep: Vec<&Series> = self.series.iter().filter(|s| !s.sweep.is_empty()).collect();
+        if !with_sweep.is_empty() {
+            let mut levels: Vec<usize> = with_sweep
+                .iter()
+                .flat_map(|s| s.sweep.iter().map(|&(n, _)| n))
+                .collect();
+            levels.sort_unstable();
+            levels.dedup();
+            charts.push((
+                "concurrency_sweep",
+                BarChart {
+                    title: "OIDBS Benchmark - Query Concurrency Scaling".into(),
+                    y_label: "Number of Queries Per Second".into(),
+                    categories: levels.iter().map(|n| format!("{} threads", n)).collect(),
+                    series: with_sweep
+                        .iter()
+                        .map(|s| {
+                            let qps = levels
+                                .iter()
+                                .map(|n| s.sweep.iter().find(|l| l.0 == *n).map(|l| l.1))
+                                .collect();
+                            (s.label.clone(), qps)
+                        })
+                        .collect(),
+                    log_scale: false,
+                    reference: None,
+                },
+            ));
+        }
+
+        if with_latencies.len() > 1 {
+            let baseline = match &self.baseline {
+                Some(b) => *with_latencies
+                    .iter()
+                    .find(|s| s.label.eq_ignore_ascii_case(b))
+                    .ok_or_else(|| OidbsError::InvalidArgs("baseline".into()))?,
+                None => with_latencies[0],
+            };
+            let mut ratios = vec![];
+            for s in with_latencies.iter().filter(|s| s.label != baseline.label) {
+                let r: Vec<Option<f64>> = (0..num_queries)
+                    .map(|i| {
+                        let v = s.latencies_us.get(i).copied().flatten()?;
+                        let b = baseline.latencies_us.get(i).copied().flatten()?;
+                        (b > 0.0).then(|| v / b)
+                    })
+                    .collect();
+                println!("Time cost ratio - {}:{}", s.label, baseline.label);
+                for (q, v) in query_labels.iter().zip(&r) {
+                    match v {
+                        Some(v) => println!("  {}: {:.2}", q, v),
+                        None => println!("  {}: n/a", q),
+                    }
+                }
+                ratios.push((s.label.clone(), r));
+            }
+            charts.push((
+                "latency_ratios",
+                BarChart {
+                    title: format!("OIDBS Benchmark - Query Time Ratio to {}", baseline.label),
+                    y_label: format!("Query Time / Query Time of {} (log scale)", baseline.label),
+                    categories: query_labels,
+                    series: ratios,
+                    log_scale: true,
+                    reference: Some(1.0),
+                },
+            ));
+        }
+        Ok(charts)
+    }
+}
+
+fn series_of_run(run: &RunResult) -> Series {
+    Series {
+        label: uppercase_first_letter(&run.target),
+        latencies_us: run
+            .queries
+            .iter()
+            .map(|q| q.best_us.map(|v| v as f64))
+            .collect(),
+        qps: run.concurrency.as_ref().map(|c| c.qps),
+        sweep: run.sweep.iter().map(|c| (c.num_threads, c.qps)).collect(),
+    }
+}
+
+/// Loads the legacy csv layouts: `db,Q1,Q2,...` for latencies or `db,<anything>` for QPS.
+fn load_csv(content: &str) -> Result<Vec<Series>, OidbsError> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or(OidbsError::Generic("empty csv result file"))?
+        .split(',')
+        .collect();
+    let is_latency = header.len() > 1
+        && header[1..].iter().all(|h| {
+            h.trim()
+                .strip_prefix('Q')
+                .is_some_and(|n| n.parse::<u32>().is_ok())
+        });
+    let is_qps = !is_latency && header.len() == 2;
+    if !is_latency && !is_qps {
+        return Err(OidbsError::Generic("unknown layout of csv result file"));
+    }
+    let mut rt = vec![];
+    for line in lines {
+        let cols: Vec<&str> = line.split(',').collect();
+        let values = cols[1..]
+            .iter()
+            .map(|v| v.trim().parse::<f64>().ok())
+            .collect::<Vec<_>>();
+        rt.push(if is_latency {
+            Series {
+                label: cols[0].to_string(),
+                latencies_us: values,
+                qps: None,
+                sweep: vec![],
+            }
+        } else {
+            Series {
+                label: cols[0].to_string(),
+                latencies_us: vec![],
+                qps: values.first().copied().flatten(),
+                sweep: vec![],
+            }
+        });
+    }
+    Ok(rt)
+}
+
+/// Merges the results of the same database from different files,
+/// or adds a new series with a numbered label if the results are duplicated.
+fn merge_series(all: &mut Vec<Series>, s: Series) {
+    let mut s = s;
+    let mut n = 1;
+    let base_label = s.label.clone();
+    loop {
+        match all.iter_mut().find(|e| e.label == s.label) {
+            Some(e)
+                if (e.latencies_us.is_empty() || s.latencies_us.is_empty())
+                    && (e.qps.is_none() || s.qps.is_none())
+                    && (e.sweep.is_empty() || s.sweep.is_empty()) =>
+            {
+                if e.latencies_us.is_empty() {
+                    e.latencies_us = s.latencies_us;
+                }
+                if e.sweep.is_empty() {
+                    e.sweep = s.sweep;
+                }
+                e.qps = e.qps.or(s.qps);
+                return;
+            }
+            Some(_) => {
+                n += 1;
+                s.label = format!("{}#{}", base_label, n);
+            }
+            None => {
+                all.push(s);
+                return;
+            }
+        }
+    }
+}
+
+const COLORS: &[&str] = &[
+    "#2ca02c", "#add8e6", "#ffa500", "#1f77b4", "#d62728", "#9467bd", "#8c564b", "#e377c2",
+];
+
+/// A grouped bar chart rendered to self-contained SVG.
+struct BarChart {
+    title: String,
+    y_label: String,
+    categories: Vec<String>,
+    /// label and values of every series, the values are indexed by categories
+    series: Vec<(String, Vec<Option<f64>>)>,
+    log_scale: bool,
+    /// an optional horizontal dashed line, e.g. 1 for ratios
+    reference: Option<f64>,
+}
+
+impl BarChart {
+    fn render_svg(&self) -> String {
+        const LEFT: f64 = 90.0;
+        const RIGHT: f64 = 20.0;
+        const TOP: f64 = 50.0;
+        const BOTTOM: f64 = 50.0;
+        const PLOT_H: f64 = 360.0;
+        let group_w = (60.0 + 24.0 * self.series.len() as f64).min(160.0);
+        let plot_w = (group_w * self.categories.len() as f64).max(480.0);
+        let legend_h = 20.0 * self.series.len() as f64 + 10.0;
+        let width = LEFT + plot_w + RIGHT;
+        let height = TOP + PLOT_H + BOTTOM + legend_h;
+        let bottom = TOP + PLOT_H;
+
+        let values = self
+            .series
+            .iter()
+            .flat_map(|(_, vs)| vs.iter().flatten().copied())
+            .chain(self.reference)
+            .filter(|v| v.is_finite() && (!self.log_scale || *v > 0.0));
+        let (min, max) = values.fold((f64::MAX, 0f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let scale = if self.log_scale {
+            Scale::log(min, max)
+        } else {
+            Scale::linear(max)
+        };
+        let y = |v: f64| bottom - scale.fraction(v) * PLOT_H;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+            w = width,
+            h = height
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="white"/><text x="{}" y="28" text-anchor="middle" font-size="16">{}</text>"#,
+            width / 2.0,
+            escape(&self.title)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text transform="translate(18,{}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            TOP + PLOT_H / 2.0,
+            escape(&self.y_label)
+        );
+        for t in scale.ticks() {
+            let ty = y(t);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{x0}" y1="{ty}" x2="{x1}" y2="{ty}" stroke="#ddd"/><text x="{tx}" y="{ly}" text-anchor="end">{label}</text>"##,
+                x0 = LEFT,
+                x1 = LEFT + plot_w,
+                ty = ty,
+                tx = LEFT - 6.0,
+                ly = ty + 4.0,
+                label = format_tick(t)
+            );
+        }
+        let nseries = self.series.len().max(1) as f64;
+        let bar_w = group_w * 0.8 / nseries;
+        for (ci, cat) in self.categories.iter().enumerate() {
+            let gx = LEFT + group_w * ci as f64 + group_w * 0.1;
+            for (si, (label, vs)) in self.series.iter().enumerate() {
+                let v = match vs.get(ci).copied().flatten() {
+                    Some(v) if v.is_finite() && (!self.log_scale || v > 0.0) => v,
+                    _ => continue,
+                };
+                let top = y(v).min(bottom);
+                let _ = writeln!(
+                    svg,
+                    r#"<rect class="bar" x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="black"><title>{} {}: {}</title></rect>"#,
+                    gx + bar_w * si as f64,
+                    top,
+                    bar_w,
+                    bottom - top,
+                    COLORS[si % COLORS.len()],
+                    escape(label),
+                    escape(cat),
+                    format_value(v)
+                );
+            }
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                LEFT + group_w * (ci as f64 + 0.5),
+                bottom + 18.0,
+                escape(cat)
+            );
+        }
+        if let Some(r) = self.reference {
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{}" y1="{ry}" x2="{}" y2="{ry}" stroke="black" stroke-dasharray="6,4"/>"#,
+                LEFT,
+                LEFT + plot_w,
+                ry = y(r)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{l}" y1="{t}" x2="{l}" y2="{b}" stroke="black"/><line x1="{l}" y1="{b}" x2="{r}" y2="{b}" stroke="black"/>"#,
+            l = LEFT,
+            t = TOP,
+            b = bottom,
+            r = LEFT + plot_w
+        );
+        for (si, (label, _)) in self.series.iter().enumerate() {
+            let ly = bottom + BOTTOM + 20.0 * si as f64;
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="14" height="14" fill="{}" stroke="black"/><text x="{}" y="{}">{}</text>"#,
+                LEFT,
+                ly - 11.0,
+                COLORS[si % COLORS.len()],
+                LEFT + 20.0,
+                ly,
+                escape(label)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+enum Scale {
+    Linear { max: f64 },
+    Log { lo: f64, hi: f64 },
+}
+
+impl Scale {
+    fn linear(max: f64) -> Self {
+        if max <= 0.0 {
+            return Scale::Linear { max: 1.0 };
+        }
+        let step = 10f64.powf((max / 5.0).log10().floor());
+        let m = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .find(|m| step * m * 5.0 >= max)
+            .unwrap_or(10.0);
+        Scale::Linear {
+            max: step * m * 5.0,
+        }
+    }
+
+    fn log(min: f64, max: f64) -> Self {
+        if max <= 0.0 || min > max {
+            return Scale::Log { lo: 1.0, hi: 10.0 };
+        }
+        let lo = min.log10().floor();
+        let hi = max.log10().ceil().max(lo + 1.0);
+        Scale::Log { lo, hi }
+    }
+
+    /// the position of `v` on the axis, from 0 to 1
+    fn fraction(&self, v: f64) -> f64 {
+        match *self {
+            Scale::Linear { max } => v / max,
+            Scale::Log { lo, hi } => (v.log10() - lo) / (hi - lo),
+        }
+    }
+
+    fn ticks(&self) -> Vec<f64> {
+        match *self {
+            Scale::Linear { max } => (0..=5).map(|i| max * i as f64 / 5.0).collect(),
+            Scale::Log { lo, hi } => (lo as i32..=hi as i32).map(|e| 10f64.powi(e)).collect(),
+        }
+    }
+}
+
+fn format_tick(v: f64) -> String {
+    if v >= 1e9 {
+        format!("{}G", v / 1e9)
+    } else if v >= 1e6 {
+        format!("{}M", v / 1e6)
+    } else if v >= 1e3 {
+        format!("{}k", v / 1e3)
+    } else {
+        format!("{}", v)
+    }
+}
+
+fn format_value(v: f64) -> String {
+    if v.fract() == 0.0 {
+        format!("{}", v)
+    } else {
+        format!("{:.2}", v)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_csv, merge_series, BarChart, Series};
+
+    #[test]
+    fn test_load_and_merge_csv() {
+        let latency = "db,Q1,Q2\nJoinBase,779,975\nTimescale,335561,436019\n";
+        let concurrency = "db,Queries Per Seconds\nJoinBase,34622.9\n";
+        let mut all = vec![];
+        for s in load_csv(latency)
+            .unwrap()
+            .into_iter()
+            .chain(load_csv(concurrency).unwrap())
+        {
+            merge_series(&mut all, s);
+        }
+        assert_eq!(all.len(), 2);
+        assert_eq!(
+            all[0],
+            Series {
+                label: "JoinBase".into(),
+                latencies_us: vec![Some(779.0), Some(975.0)],
+                qps: Some(34622.9),
+                sweep: vec![],
+            }
+        );
+        // duplicated results are kept as another series
+        merge_series(&mut all, load_csv(concurrency).unwrap().pop().unwrap());
+        assert_eq!(all[2].label, "JoinBase#2");
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let chart = BarChart {
+            title: "a <title>".into(),
+            y_label: "us".into(),
+            categories: vec!["Q1".into(), "Q2".into()],
+            series: vec![
+                ("A".into(), vec![Some(10.0), Some(2000.0)]),
+                ("B".into(), vec![Some(30.0), None]),
+            ],
+            log_scale: true,
+            reference: Some(1.0),
+        };
+        let svg = chart.render_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("a &lt;title&gt;"));
+        assert_eq!(svg.matches(r#"class="bar""#).count(), 3);
+    }
+}
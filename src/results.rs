@@ -0,0 +1,185 @@
+use crate::{error::OidbsError, sampler::ResourceUsage};
+use serde_derive::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+/// The machine-readable result document of one bench run,
+/// which is intended to be archived and compared across runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunResult {
+    pub oidbs_version: String,
+    /// the start time of the run, in RFC 3339
+    pub timestamp: String,
+    pub model: String,
+    pub target: String,
+    pub server_version: Option<String>,
+    pub host: HostInfo,
+    /// all CLI parameters of the run
+    pub parameters: serde_json::Value,
+    pub measurement_mode: String,
+    #[serde(default)]
+    pub queries: Vec<QueryResult>,
+    pub concurrency: Option<ConcurrencyResult>,
+    /// the results at every level of the concurrency sweep
+    #[serde(default)]
+    pub sweep: Vec<ConcurrencyResult>,
+    /// the number of threads at the knee point of the sweep
+    #[serde(default)]
+    pub knee_threads: Option<usize>,
+    /// the snapshots at every interval of a duration-based run
+    #[serde(default)]
+    pub snapshots: Vec<IntervalSnapshot>,
+    #[serde(default)]
+    pub drift: Option<SoakDrift>,
+    /// the sampled resource usage of the server
+    #[serde(default)]
+    pub resources: Option<ResourceUsage>,
+}
+
+/// the result of one query in the latency measurement mode, all times are in microseconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResult {
+    /// the stable id of the query, empty in the documents of older versions
+    #[serde(default)]
+    pub id: String,
+    pub desc: String,
+    pub sql: String,
+    /// all successful runs, in the order of execution
+    pub samples_us: Vec<u64>,
+    pub best_us: Option<u64>,
+    pub mean_us: Option<u64>,
+    pub num_errors: u32,
+    pub num_timeouts: u32,
+}
+
+/// the result of the formal run in the concurrency measurement mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcurrencyResult {
+    pub sql: String,
+    pub num_threads: usize,
+    pub qps: f64,
+    pub num_ok: u64,
+    pub num_errors: u64,
+    pub num_timeouts: u64,
+    pub time_us: u64,
+    #[serde(default)]
+    pub p50_us: Option<u64>,
+    #[serde(default)]
+    pub p95_us: Option<u64>,
+    #[serde(default)]
+    pub p99_us: Option<u64>,
+    /// latencies of all successful queries, in microseconds
+    pub samples_us: Vec<u64>,
+}
+
+/// the statistics of one interval of a duration-based run, all times are in microseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntervalSnapshot {
+    /// the offset of the interval from the start of the run, in seconds
+    pub start_s: f64,
+    /// the length of the interval in seconds, the last one may be shorter
+    pub len_s: f64,
+    pub qps: f64,
+    pub num_ok: u64,
+    pub num_errors: u64,
+    pub num_timeouts: u64,
+    pub mean_us: Option<u64>,
+    pub p50_us: Option<u64>,
+    pub p99_us: Option<u64>,
+}
+
+/// the relative changes from the start to the end of a soak run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoakDrift {
+    pub qps_change: Option<f64>,
+    pub p99_change: Option<f64>,
+    /// whether any change is beyond the drift threshold
+    pub drifted: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub cpu_model: String,
+    pub num_cores: usize,
+    pub kernel: String,
+    pub mem_total_kb: u64,
+}
+
+impl HostInfo {
+    /// Collects the info of the client host from `/proc`, missed items are left as default.
+    pub fn collect() -> Self {
+        let read = |p: &str| fs::read_to_string(p).unwrap_or_default();
+        let (cpu_model, num_cores) = parse_cpuinfo(&read("/proc/cpuinfo"));
+        HostInfo {
+            hostname: read("/proc/sys/kernel/hostname").trim().to_string(),
+            cpu_model,
+            num_cores,
+            kernel: read("/proc/sys/kernel/osrelease").trim().to_string(),
+            mem_total_kb: parse_meminfo(&read("/proc/meminfo"), "MemTotal").unwrap_or_default(),
+        }
+    }
+}
+
+/// returns the model name of the first cpu and the number of logical cores
+fn parse_cpuinfo(cpuinfo: &str) -> (String, usize) {
+    let mut model = String::new();
+    let mut cores = 0;
+    for line in cpuinfo.lines() {
+        if let Some((k, v)) = line.split_once(':') {
+            match k.trim() {
+                "processor" => cores += 1,
+                "model name" if model.is_empty() => model = v.trim().to_string(),
+                _ => {}
+            }
+        }
+    }
+    (model, cores)
+}
+
+/// returns the value of the `key` in kB
+pub(crate) fn parse_meminfo(meminfo: &str, key: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        if k.trim() == key {
+            v.trim().trim_end_matches("kB").trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+pub(crate) fn as_micros(d: Duration) -> u64 {
+    d.as_micros() as u64
+}
+
+impl RunResult {
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<(), OidbsError> {
+        let path = path.as_ref();
+        let file = fs::File::create(path).map_err(|e| OidbsError::file(path, e))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn read_from<P: AsRef<Path>>(path: P) -> Result<Self, OidbsError> {
+        let path = path.as_ref();
+        let file = fs::File::open(path).map_err(|e| OidbsError::file(path, e))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cpuinfo, parse_meminfo};
+
+    #[test]
+    fn test_parse_proc() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: AMD EPYC 7R13 Processor\n\nprocessor\t: 1\nmodel name\t: AMD EPYC 7R13 Processor\n";
+        assert_eq!(
+            parse_cpuinfo(cpuinfo),
+            ("AMD EPYC 7R13 Processor".to_string(), 2)
+        );
+        let meminfo = "MemTotal:       263876536 kB\nMemFree:        254532428 kB\n";
+        assert_eq!(parse_meminfo(meminfo, "MemTotal"), Some(263876536));
+        assert_eq!(parse_meminfo(meminfo, "SwapTotal"), None);
+    }
+}
@@ -0,0 +1,500 @@
+//! Sampling of the resource usage of a local server during `gen`, `import` and `bench`.
+//!
+//! The server is given by its PID, whose counters are read from `/proc/<pid>`,
+//! or by its cgroup(v2) directory, whose counters are read from `cpu.stat`, `memory.current`
+//! and `io.stat`. The network counters are only available for a PID, which are the ones
+//! of its network namespace in `/proc/<pid>/net/dev`.
+
+use crate::{error::OidbsError, results::parse_meminfo};
+use clap::Args;
+use comfy_table::{Row, Table};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// the clock ticks per second of the cpu times in `/proc/<pid>/stat`, which is 100 on all
+/// mainstream Linux platforms
+const CLOCK_TICKS: f64 = 100.0;
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct SamplerArgs {
+    /// the PID of a local server to sample its CPU time, RSS, disk and network bytes from `/proc` during the run
+    #[clap(long)]
+    pub(crate) server_pid: Option<u32>,
+
+    /// the cgroup(v2) directory of a local server to sample its resource usage during the run instead of a PID, e.g. `/sys/fs/cgroup/system.slice/joinbase.service`
+    #[clap(long)]
+    pub(crate) server_cgroup: Option<String>,
+
+    /// the interval in milliseconds to sample the resource usage of the server
+    #[clap(long, default_value_t = 1000)]
+    pub(crate) sample_interval_ms: u64,
+
+    /// the path to write the JSON document of the sampled resource usage
+    #[clap(long)]
+    pub(crate) resources_output: Option<String>,
+}
+
+/// the CLI defaults, without sampling
+impl Default for SamplerArgs {
+    fn default() -> Self {
+        Self {
+            server_pid: None,
+            server_cgroup: None,
+            sample_interval_ms: 1000,
+            resources_output: None,
+        }
+    }
+}
+
+builder_methods!(SamplerArgs {
+    sample_interval_ms: u64,
+} optional {
+    server_pid: u32,
+    server_cgroup: String,
+    resources_output: String,
+});
+
+/// the cumulative counters of the server at one time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceSample {
+    /// the offset from the start of sampling, in seconds
+    pub t_s: f64,
+    /// user and system CPU time, in seconds
+    pub cpu_s: f64,
+    pub rss_bytes: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub net_rx_bytes: Option<u64>,
+    pub net_tx_bytes: Option<u64>,
+}
+
+/// the resource usage of the server within one phase of the run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseSummary {
+    pub phase: String,
+    pub time_s: f64,
+    pub cpu_s: f64,
+    /// the average number of busy cores
+    pub cores: f64,
+    pub max_rss_bytes: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub net_rx_bytes: Option<u64>,
+    pub net_tx_bytes: Option<u64>,
+    /// successful queries per second of CPU time of the server
+    pub queries_per_core_sec: Option<f64>,
+    /// bytes written to disk by the server per ingested row
+    pub write_bytes_per_row: Option<f64>,
+}
+
+/// the sampled time series and the per-phase summaries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// `pid:<pid>` or `cgroup:<dir>`
+    pub source: String,
+    pub interval_ms: u64,
+    pub samples: Vec<ResourceSample>,
+    pub phases: Vec<PhaseSummary>,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    Pid(u32),
+    Cgroup(PathBuf),
+}
+
+impl Source {
+    fn describe(&self) -> String {
+        match self {
+            Source::Pid(pid) => format!("pid:{}", pid),
+            Source::Cgroup(dir) => format!("cgroup:{}", dir.display()),
+        }
+    }
+
+    /// Reads the counters, `None` if the server is gone.
+    fn read(&self, t_s: f64) -> Option<ResourceSample> {
+        let read = |p: PathBuf| fs::read_to_string(p).ok();
+        match self {
+            Source::Pid(pid) => {
+                let dir = PathBuf::from(format!("/proc/{}", pid));
+                let cpu_ticks = parse_stat_cpu_ticks(&read(dir.join("stat"))?)?;
+                let status = read(dir.join("status")).unwrap_or_default();
+                // `io` is only readable by the owner of the process or root
+                let io = read(dir.join("io")).unwrap_or_default();
+                let net = read(dir.join("net/dev")).and_then(|s| parse_net_dev(&s));
+                Some(ResourceSample {
+                    t_s,
+                    cpu_s: cpu_ticks as f64 / CLOCK_TICKS,
+                    rss_bytes: parse_meminfo(&status, "VmRSS").unwrap_or_default() * 1024,
+                    read_bytes: parse_meminfo(&io, "read_bytes").unwrap_or_default(),
+                    write_bytes: parse_meminfo(&io, "write_bytes").unwrap_or_default(),
+                    net_rx_bytes: net.map(|n| n.0),
+                    net_tx_bytes: net.map(|n| n.1),
+                })
+            }
+            Source::Cgroup(dir) => {
+                let cpu_stat = read(dir.join("cpu.stat"))?;
+                let usage_usec = parse_flat_keyed(&cpu_stat, "usage_usec")?;
+                let (read_bytes, write_bytes) =
+                    parse_io_stat(&read(dir.join("io.stat")).unwrap_or_default());
+                Some(ResourceSample {
+                    t_s,
+                    cpu_s: usage_usec as f64 / 1e6,
+                    rss_bytes: read(dir.join("memory.current"))
+                        .and_then(|s| s.trim().parse().ok())
+                        .unwrap_or_default(),
+                    read_bytes,
+                    write_bytes,
+                    net_rx_bytes: None,
+                    net_tx_bytes: None,
+                })
+            }
+        }
+    }
+}
+
+/// the sum of `utime` and `stime` in `/proc/<pid>/stat`, in clock ticks
+fn parse_stat_cpu_ticks(stat: &str) -> Option<u64> {
+    // the command name in the parentheses may contain spaces
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // `state` is the 3rd field, `utime` and `stime` are the 14th and 15th ones
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// the received and transmitted bytes of all interfaces in `/proc/<pid>/net/dev`
+fn parse_net_dev(dev: &str) -> Option<(u64, u64)> {
+    let mut rt = (0, 0);
+    for line in dev.lines().skip(2) {
+        let (_, counters) = line.split_once(':')?;
+        let counters: Vec<u64> = counters
+            .split_whitespace()
+            .map(|c| c.parse().ok())
+            .collect::<Option<_>>()?;
+        rt.0 += counters.first()?;
+        rt.1 += counters.get(8)?;
+    }
+    Some(rt)
+}
+
+/// the value of `key` in a flat keyed file of cgroup v2, like `cpu.stat`
+fn parse_flat_keyed(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        (k == key).then(|| v.trim().parse().ok()).flatten()
+    })
+}
+
+/// the read and written bytes of all devices in `io.stat` of cgroup v2
+fn parse_io_stat(io_stat: &str) -> (u64, u64) {
+    let mut rt = (0, 0);
+    for kv in io_stat.split_whitespace() {
+        match kv.split_once('=') {
+            Some(("rbytes", v)) => rt.0 += v.parse::<u64>().unwrap_or_default(),
+            Some(("wbytes", v)) => rt.1 += v.parse::<u64>().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    rt
+}
+
+#[derive(Default)]
+struct Series {
+    samples: Vec<ResourceSample>,
+    /// the names of the phases and the indexes of their first samples
+    phases: Vec<(String, usize)>,
+}
+
+/// Samples the resource usage of the server in a background thread until finished.
+pub struct Sampler {
+    source: Source,
+    interval: Duration,
+    start: Instant,
+    series: Arc<Mutex<Series>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Sampler {
+    /// Starts sampling, `None` if no server is given.
+    pub fn start(args: &SamplerArgs) -> Result<Option<Self>, OidbsError> {
+        let source = match (args.server_pid, &args.server_cgroup) {
+            (Some(_), Some(_)) => {
+                return Err(OidbsError::InvalidArgs(
+                    "server_pid and server_cgroup, only one is allowed".into(),
+                ))
+            }
+            (Some(pid), None) => Source::Pid(pid),
+            (None, Some(dir)) => Source::Cgroup(PathBuf::from(dir)),
+            (None, None) => return Ok(None),
+        };
+        if args.sample_interval_ms == 0 {
+            return Err(OidbsError::InvalidArgs("sample_interval_ms".into()));
+        }
+        let first = source
+            .read(0.0)
+            .ok_or_else(|| OidbsError::InvalidArgs(format!("server {}", source.describe())))?;
+        println!("[sampler] to sample the resource usage of {}", source.describe());
+
+        let interval = Duration::from_millis(args.sample_interval_ms);
+        let start = Instant::now();
+        let series = Arc::new(Mutex::new(Series {
+            samples: vec![first],
+            phases: vec![],
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (source, series, stop) = (source.clone(), series.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut next = interval;
+                while !stop.load(Ordering::Relaxed) {
+                    let elapsed = start.elapsed();
+                    if elapsed < next {
+                        // wakes up often to stop soon
+                        thread::sleep((next - elapsed).min(Duration::from_millis(100)));
+                        continue;
+                    }
+                    next += interval;
+                    match source.read(start.elapsed().as_secs_f64()) {
+                        Some(s) => series
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .samples
+                            .push(s),
+                        None => {
+                            log::warn!("[sampler] {} is gone", source.describe());
+                            break;
+                        }
+                    }
+                }
+            })
+        };
+        Ok(Some(Sampler {
+            source,
+            interval,
+            start,
+            series,
+            stop,
+            handle,
+        }))
+    }
+
+    /// Takes a sample now and starts a new phase from it.
+    pub fn phase(&self, name: &str) {
+        let sample = self.source.read(self.start.elapsed().as_secs_f64());
+        let mut series = self.series.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(s) = sample {
+            series.samples.push(s);
+        }
+        let first = series.samples.len() - 1;
+        series.phases.push((name.to_string(), first));
+    }
+
+    /// Stops sampling, and summarizes every phase.
+    pub fn finish(self) -> ResourceUsage {
+        self.stop.store(true, Ordering::Relaxed);
+        if self.handle.join().is_err() {
+            log::warn!("[sampler] the sampling thread panicked");
+        }
+        let mut series =
+            std::mem::take(&mut *self.series.lock().unwrap_or_else(PoisonError::into_inner));
+        if let Some(s) = self.source.read(self.start.elapsed().as_secs_f64()) {
+            series.samples.push(s);
+        }
+        let phases = series
+            .phases
+            .iter()
+            .enumerate()
+            .map(|(i, (name, first))| {
+                let last = series
+                    .phases
+                    .get(i + 1)
+                    .map_or(series.samples.len() - 1, |p| p.1);
+                summarize(name, &series.samples[*first..=last])
+            })
+            .collect();
+        ResourceUsage {
+            source: self.source.describe(),
+            interval_ms: self.interval.as_millis() as u64,
+            samples: series.samples,
+            phases,
+        }
+    }
+}
+
+fn summarize(phase: &str, samples: &[ResourceSample]) -> PhaseSummary {
+    let (a, b) = (&samples[0], &samples[samples.len() - 1]);
+    let time_s = b.t_s - a.t_s;
+    let cpu_s = b.cpu_s - a.cpu_s;
+    let diff = |a: Option<u64>, b: Option<u64>| Some(b?.saturating_sub(a?));
+    PhaseSummary {
+        phase: phase.to_string(),
+        time_s,
+        cpu_s,
+        cores: if time_s > 0.0 { cpu_s / time_s } else { 0.0 },
+        max_rss_bytes: samples.iter().map(|s| s.rss_bytes).max().unwrap_or_default(),
+        read_bytes: b.read_bytes.saturating_sub(a.read_bytes),
+        write_bytes: b.write_bytes.saturating_sub(a.write_bytes),
+        net_rx_bytes: diff(a.net_rx_bytes, b.net_rx_bytes),
+        net_tx_bytes: diff(a.net_tx_bytes, b.net_tx_bytes),
+        queries_per_core_sec: None,
+        write_bytes_per_row: None,
+    }
+}
+
+fn fmt_bytes(n: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut v = n as f64;
+    let mut i = 0;
+    while v >= 1024.0 && i < UNITS.len() - 1 {
+        v /= 1024.0;
+        i += 1;
+    }
+    format!("{:.1}{}", v, UNITS[i])
+}
+
+impl ResourceUsage {
+    fn phase_mut(&mut self, phase: &str) -> Option<&mut PhaseSummary> {
+        self.phases.iter_mut().find(|p| p.phase == phase)
+    }
+
+    /// Attributes the successful queries to the phase, for the queries per core-second.
+    pub fn set_queries(&mut self, phase: &str, num_queries: u64) {
+        if let Some(p) = self.phase_mut(phase) {
+            p.queries_per_core_sec = (p.cpu_s > 0.0).then(|| num_queries as f64 / p.cpu_s);
+        }
+    }
+
+    /// Attributes the ingested rows to the phase, for the bytes written per row.
+    pub fn set_rows(&mut self, phase: &str, num_rows: u64) {
+        if let Some(p) = self.phase_mut(phase) {
+            p.write_bytes_per_row = (num_rows > 0).then(|| p.write_bytes as f64 / num_rows as f64);
+        }
+    }
+
+    /// Prints the per-phase summaries, and writes the document to `output` if given.
+    pub fn report(&self, output: Option<&str>) -> Result<(), OidbsError> {
+        self.print();
+        if let Some(output) = output {
+            self.write_to(output)?;
+            println!("resource usage written to {}", output);
+        }
+        Ok(())
+    }
+
+    /// Prints the per-phase summaries.
+    pub fn print(&self) {
+        let mut table = Table::new();
+        table.load_preset("||--+-++|    ++++++");
+        table.set_header(Row::from(vec![
+            "Phase",
+            "Time",
+            "CPU Time",
+            "Cores",
+            "Max RSS",
+            "Disk Read",
+            "Disk Written",
+            "Net RX",
+            "Net TX",
+            "Queries/Core-sec",
+            "Written/Row",
+        ]));
+        let opt = |v: Option<u64>| v.map_or("n/a".to_string(), fmt_bytes);
+        let opt_f = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.2}", v));
+        for p in &self.phases {
+            table.add_row(vec![
+                p.phase.clone(),
+                format!("{:.1}s", p.time_s),
+                format!("{:.2}s", p.cpu_s),
+                format!("{:.2}", p.cores),
+                fmt_bytes(p.max_rss_bytes),
+                fmt_bytes(p.read_bytes),
+                fmt_bytes(p.write_bytes),
+                opt(p.net_rx_bytes),
+                opt(p.net_tx_bytes),
+                opt_f(p.queries_per_core_sec),
+                opt_f(p.write_bytes_per_row),
+            ]);
+        }
+        println!("resource usage of the server({}):\n{}", self.source, table);
+    }
+
+    /// Writes the document to `output` in JSON.
+    pub fn write_to(&self, output: &str) -> Result<(), OidbsError> {
+        let file = fs::File::create(output).map_err(|e| OidbsError::file(output, e))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_io_stat, parse_net_dev, parse_stat_cpu_ticks, summarize, ResourceSample,
+        ResourceUsage, Source,
+    };
+
+    #[test]
+    fn test_parse_proc_counters() {
+        let stat = "1234 (join base) S 1 1234 1234 0 -1 4194560 5000 0 0 0 250 75 0 0 20 0 8 0 100 1000000 2000 18446744073709551615";
+        assert_eq!(parse_stat_cpu_ticks(stat), Some(325));
+        assert_eq!(parse_stat_cpu_ticks("1234 (x) S 1"), None);
+
+        let dev = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  1000      10    0    0    0     0          0         0     1000      10    0    0    0     0       0          0
+  eth0:  5000      50    0    0    0     0          0         0     3000      30    0    0    0     0       0          0
+";
+        assert_eq!(parse_net_dev(dev), Some((6000, 4000)));
+
+        let io_stat = "8:0 rbytes=1024 wbytes=4096 rios=1 wios=2 dbytes=0 dios=0\n8:16 rbytes=1 wbytes=2 rios=1 wios=1 dbytes=0 dios=0\n";
+        assert_eq!(parse_io_stat(io_stat), (1025, 4098));
+    }
+
+    #[test]
+    fn test_summarize_phases() {
+        let sample = |t_s: f64, cpu_s: f64, rss: u64, written: u64| ResourceSample {
+            t_s,
+            cpu_s,
+            rss_bytes: rss,
+            read_bytes: 0,
+            write_bytes: written,
+            net_rx_bytes: Some(t_s as u64 * 100),
+            net_tx_bytes: None,
+        };
+        let samples = vec![
+            sample(0.0, 1.0, 100, 0),
+            sample(1.0, 2.5, 300, 1000),
+            sample(2.0, 4.0, 200, 3000),
+        ];
+        let p = summarize("ingest", &samples);
+        assert_eq!(p.time_s, 2.0);
+        assert_eq!(p.cpu_s, 3.0);
+        assert_eq!(p.cores, 1.5);
+        assert_eq!(p.max_rss_bytes, 300);
+        assert_eq!(p.write_bytes, 3000);
+        assert_eq!(p.net_rx_bytes, Some(200));
+        assert_eq!(p.net_tx_bytes, None);
+
+        let mut usage = ResourceUsage {
+            source: Source::Pid(1).describe(),
+            interval_ms: 1000,
+            samples,
+            phases: vec![p],
+        };
+        usage.set_rows("ingest", 300);
+        usage.set_queries("ingest", 600);
+        assert_eq!(usage.phases[0].write_bytes_per_row, Some(10.0));
+        assert_eq!(usage.phases[0].queries_per_core_sec, Some(200.0));
+    }
+}
@@ -0,0 +1,516 @@
+//! The abstract schema of a model, `schema.toml` in the model directory.
+//!
+//! ```toml
+//! database = "benchmark"
+//! table = "pstations"
+//!
+//! [[columns]]
+//! name = "station_id"
+//! type = "uint32"
+//!
+//! [[columns]]
+//! name = "ts"
+//! type = "timestamp"
+//!
+//! [partition]
+//! time_column = "ts"
+//! interval = "1h"
+//! space_column = "station_id"
+//! space_partitions = 2
+//!
+//! [targets.timescale]
+//! types = { station_id = "integer" }
+//! extra = "CREATE INDEX ON pstations (station_id, ts DESC);"
+//! ```
+//!
+//! The columns are rendered to the DDL of every target with the type mappings below,
+//! so all targets store the dataset in equivalent physical types:
+//!
+//! | abstract    | JoinBase   | Timescale/PostgreSQL          | ClickHouse |
+//! |-------------|------------|-------------------------------|------------|
+//! | `int8`      | `Int8`     | `smallint`                    | `Int8`     |
+//! | `int16`     | `Int16`    | `smallint`                    | `Int16`    |
+//! | `int32`     | `Int32`    | `integer`                     | `Int32`    |
+//! | `int64`     | `Int64`    | `bigint`                      | `Int64`    |
+//! | `uint8`     | `UInt8`    | `smallint`                    | `UInt8`    |
+//! | `uint16`    | `UInt16`   | `integer`                     | `UInt16`   |
+//! | `uint32`    | `UInt32`   | `bigint`                      | `UInt32`   |
+//! | `uint64`    | `UInt64`   | `numeric(20)`                 | `UInt64`   |
+//! | `float32`   | `Float32`  | `real`                        | `Float32`  |
+//! | `float64`   | `Float64`  | `double precision`            | `Float64`  |
+//! | `string`    | `String`   | `text`                        | `String`   |
+//! | `timestamp` | `DateTime` | `timestamp without time zone` | `DateTime` |
+//!
+//! PostgreSQL has no unsigned types, so they are widened to the next signed ones.
+//! The partitioning by the time column is `PARTITION BY ymdh(ts)` for JoinBase, a hypertable
+//! for TimescaleDB, an index on the time column for a plain PostgreSQL table, and a `MergeTree`
+//! partitioned and ordered by the time for ClickHouse. The chunks of the hypertable keep the
+//! default interval of TimescaleDB, unless `targets.timescale.chunk_time_interval` is given.
+//!
+//! A hand-written `schemas/<target>` still takes precedence over the rendered DDL.
+//! `targets.<target>` tunes the rendered one for the target: `types` overrides the types of
+//! columns, `partition_by` the partitioning clause, and `extra` is appended.
+
+use crate::error::{OidbsError, OidbsResult};
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// the file of the abstract schema in the model directory
+pub const SCHEMA_FILE: &str = "schema.toml";
+
+/// the targets which the abstract schema is rendered for
+pub const RENDERED_TARGETS: &[&str] = &["joinbase", "timescale", "postgresql", "clickhouse"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
+    String,
+    Timestamp,
+}
+
+impl ColumnType {
+    /// the physical type of the target, see the mappings in the module doc
+    pub fn sql_type(self, target: &str) -> &'static str {
+        use ColumnType::*;
+        match target {
+            "timescale" | "postgresql" => match self {
+                Int8 | Int16 | UInt8 => "smallint",
+                Int32 | UInt16 => "integer",
+                Int64 | UInt32 => "bigint",
+                UInt64 => "numeric(20)",
+                Float32 => "real",
+                Float64 => "double precision",
+                String => "text",
+                Timestamp => "timestamp without time zone",
+            },
+            _ => match self {
+                Int8 => "Int8",
+                Int16 => "Int16",
+                Int32 => "Int32",
+                Int64 => "Int64",
+                UInt8 => "UInt8",
+                UInt16 => "UInt16",
+                UInt32 => "UInt32",
+                UInt64 => "UInt64",
+                Float32 => "Float32",
+                Float64 => "Float64",
+                String => "String",
+                Timestamp => "DateTime",
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub data_type: ColumnType,
+    #[serde(default)]
+    pub not_null: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartitionSpec {
+    pub time_column: String,
+    /// the length of a partition, `<n>m`, `<n>h` or `<n>d`
+    pub interval: String,
+    /// the column of the space partitioning of TimescaleDB
+    pub space_column: Option<String>,
+    pub space_partitions: Option<u32>,
+}
+
+/// the tuning of the rendered DDL for one target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetTuning {
+    /// the types of columns, which override the mapped ones
+    #[serde(default)]
+    pub types: BTreeMap<String, String>,
+    /// the partitioning clause, e.g. `ymdh12(pickup_datetime)` for JoinBase
+    pub partition_by: Option<String>,
+    /// the statements appended to the rendered DDL, e.g. indexes
+    pub extra: Option<String>,
+    /// the chunk interval of the TimescaleDB hypertable, in the form of the partition interval
+    pub chunk_time_interval: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaSpec {
+    #[serde(default = "default_database")]
+    pub database: String,
+    pub table: String,
+    pub columns: Vec<ColumnSpec>,
+    pub partition: Option<PartitionSpec>,
+    /// the sorting key of ClickHouse, default to the time column
+    #[serde(default)]
+    pub order_by: Vec<String>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, TargetTuning>,
+}
+
+fn default_database() -> String {
+    "benchmark".into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntervalUnit {
+    Minute,
+    Hour,
+    Day,
+}
+
+/// Parses the partition interval like `1h` or `3d`.
+fn parse_interval(s: &str) -> Option<(u32, IntervalUnit)> {
+    let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit())?);
+    let n = n.parse().ok().filter(|n| *n > 0)?;
+    let unit = match unit {
+        "m" => IntervalUnit::Minute,
+        "h" => IntervalUnit::Hour,
+        "d" => IntervalUnit::Day,
+        _ => return None,
+    };
+    Some((n, unit))
+}
+
+impl SchemaSpec {
+    pub fn parse(s: &str) -> OidbsResult<Self> {
+        let spec: SchemaSpec = toml::from_str(s)
+            .map_err(|e| OidbsError::InvalidDdl(format!("{}: {}", SCHEMA_FILE, e)))?;
+        let invalid =
+            |msg: String| Err(OidbsError::InvalidDdl(format!("{}: {}", SCHEMA_FILE, msg)));
+        let mut names = HashSet::new();
+        for c in &spec.columns {
+            if c.name.is_empty() || !names.insert(c.name.as_str()) {
+                return invalid(format!("empty or duplicated column '{}'", c.name));
+            }
+        }
+        if names.is_empty() {
+            return invalid("no columns".into());
+        }
+        if let Some(p) = &spec.partition {
+            match spec.column(&p.time_column) {
+                Some(c) if c.data_type == ColumnType::Timestamp => {}
+                _ => return invalid(format!("time column '{}'", p.time_column)),
+            }
+            if parse_interval(&p.interval).is_none() {
+                return invalid(format!("partition interval '{}'", p.interval));
+            }
+            if let Some(c) = &p.space_column {
+                if !names.contains(c.as_str()) {
+                    return invalid(format!("space column '{}'", c));
+                }
+            }
+        }
+        for c in &spec.order_by {
+            if !names.contains(c.as_str()) {
+                return invalid(format!("order by column '{}'", c));
+            }
+        }
+        for (target, tuning) in &spec.targets {
+            if !RENDERED_TARGETS.contains(&target.as_str()) {
+                return invalid(format!("target '{}'", target));
+            }
+            for c in tuning.types.keys() {
+                if !names.contains(c.as_str()) {
+                    return invalid(format!("type override of column '{}'", c));
+                }
+            }
+            match &tuning.chunk_time_interval {
+                Some(i) if target != "timescale" || parse_interval(i).is_none() => {
+                    return invalid(format!("chunk_time_interval '{}' of {}", i, target));
+                }
+                _ => {}
+            }
+        }
+        Ok(spec)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSpec> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// the table qualified with the database for JoinBase and ClickHouse, or the one in the
+    /// database of the connection for PostgreSQL
+    fn qualified_table(&self, target: &str) -> String {
+        match target {
+            "timescale" | "postgresql" => self.table.clone(),
+            _ => format!("{}.{}", self.database, self.table),
+        }
+    }
+
+    fn partition_by(&self, target: &str, tuning: &TargetTuning) -> OidbsResult<Option<String>> {
+        if tuning.partition_by.is_some() {
+            return Ok(tuning.partition_by.clone());
+        }
+        let p = match &self.partition {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        // checked by parse
+        let (n, unit) = parse_interval(&p.interval).unwrap_or((1, IntervalUnit::Day));
+        let ts = &p.time_column;
+        let unsupported = || {
+            OidbsError::Unsupported(format!(
+                "partition interval {} of {}, set targets.{}.partition_by",
+                p.interval, target, target
+            ))
+        };
+        Ok(Some(match target {
+            "joinbase" => match (n, unit) {
+                (1, IntervalUnit::Minute) => format!("ymdhm({})", ts),
+                (1, IntervalUnit::Hour) => format!("ymdh({})", ts),
+                (1, IntervalUnit::Day) => format!("yyyymmdd({})", ts),
+                _ => return Err(unsupported()),
+            },
+            "clickhouse" => match (n, unit) {
+                (1, IntervalUnit::Minute) => format!("toStartOfMinute({})", ts),
+                (1, IntervalUnit::Hour) => format!("toStartOfHour({})", ts),
+                (1, IntervalUnit::Day) => format!("toYYYYMMDD({})", ts),
+                (n, u) => format!(
+                    "toStartOfInterval({}, INTERVAL {} {})",
+                    ts,
+                    n,
+                    interval_unit_name(u)
+                ),
+            },
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Renders the DDL of the target, which drops and creates the table.
+    pub fn render(&self, target: &str) -> OidbsResult<String> {
+        if !RENDERED_TARGETS.contains(&target) {
+            return Err(OidbsError::Unsupported(format!(
+                "schema of target {}",
+                target
+            )));
+        }
+        let tuning = self.targets.get(target).cloned().unwrap_or_default();
+        let is_pg = matches!(target, "timescale" | "postgresql");
+        let time_column = self.partition.as_ref().map(|p| p.time_column.as_str());
+        let table = self.qualified_table(target);
+
+        let mut sql = String::new();
+        match target {
+            "timescale" => sql.push_str("CREATE EXTENSION IF NOT EXISTS timescaledb;\n"),
+            "clickhouse" => sql.push_str(&format!(
+                "CREATE DATABASE IF NOT EXISTS {};\n",
+                self.database
+            )),
+            _ => {}
+        }
+        sql.push_str(&format!("DROP TABLE IF EXISTS {};\n\n", table));
+        sql.push_str(&format!("CREATE TABLE {}(\n", table));
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|c| {
+                let ty = tuning
+                    .types
+                    .get(&c.name)
+                    .map_or(c.data_type.sql_type(target), |t| t.as_str());
+                // the time column of a hypertable should be not null
+                let not_null = is_pg && (c.not_null || time_column == Some(c.name.as_str()));
+                format!(
+                    "    {} {}{}",
+                    c.name,
+                    ty,
+                    if not_null { " NOT NULL" } else { "" }
+                )
+            })
+            .collect();
+        sql.push_str(&columns.join(",\n"));
+        sql.push_str("\n)");
+
+        let partition_by = self.partition_by(target, &tuning)?;
+        match target {
+            "joinbase" => {
+                if let Some(p) = partition_by {
+                    sql.push_str(&format!("\nPARTITION BY {}", p));
+                }
+                sql.push_str(";\n");
+            }
+            "clickhouse" => {
+                sql.push_str("\nENGINE = MergeTree()");
+                if let Some(p) = partition_by {
+                    sql.push_str(&format!("\nPARTITION BY {}", p));
+                }
+                let order_by: Vec<&str> = match (&self.order_by[..], time_column) {
+                    ([], Some(ts)) => vec![ts],
+                    (cs, _) => cs.iter().map(|c| c.as_str()).collect(),
+                };
+                sql.push_str(&format!("\nORDER BY ({});\n", order_by.join(", ")));
+            }
+            "timescale" => {
+                sql.push_str(";\n");
+                if let Some(p) = &self.partition {
+                    let space = match (&p.space_column, p.space_partitions) {
+                        (Some(c), n) => format!(", '{}', {}", c, n.unwrap_or(2)),
+                        (None, _) => String::new(),
+                    };
+                    // checked by parse
+                    let chunk = match tuning
+                        .chunk_time_interval
+                        .as_deref()
+                        .and_then(parse_interval)
+                    {
+                        Some((n, u)) => format!(
+                            ", chunk_time_interval => interval '{} {}'",
+                            n,
+                            interval_unit_name(u)
+                        ),
+                        None => String::new(),
+                    };
+                    sql.push_str(&format!(
+                        "SELECT create_hypertable('{}', '{}'{}{}, create_default_indexes=>FALSE);\n",
+                        self.table, p.time_column, space, chunk
+                    ));
+                }
+            }
+            _ => {
+                sql.push_str(";\n");
+                if let Some(ts) = time_column {
+                    sql.push_str(&format!("CREATE INDEX ON {} ({});\n", self.table, ts));
+                }
+            }
+        }
+        if let Some(extra) = &tuning.extra {
+            sql.push('\n');
+            sql.push_str(extra.trim());
+            sql.push('\n');
+        }
+        Ok(sql)
+    }
+}
+
+fn interval_unit_name(unit: IntervalUnit) -> &'static str {
+    match unit {
+        IntervalUnit::Minute => "minute",
+        IntervalUnit::Hour => "hour",
+        IntervalUnit::Day => "day",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SchemaSpec, RENDERED_TARGETS};
+    use crate::ddl::parse_schema;
+    use crate::error::OidbsError;
+
+    #[test]
+    fn test_render_schema() {
+        let spec = SchemaSpec::parse(
+            r#"
+table = "t"
+
+[[columns]]
+name = "id"
+type = "uint32"
+
+[[columns]]
+name = "v"
+type = "float32"
+not_null = true
+
+[[columns]]
+name = "ts"
+type = "timestamp"
+
+[partition]
+time_column = "ts"
+interval = "1h"
+space_column = "id"
+
+[targets.timescale]
+types = { v = "NUMERIC" }
+extra = "CREATE INDEX ON t (id, ts DESC);"
+chunk_time_interval = "1h"
+"#,
+        )
+        .unwrap();
+        assert_eq!(spec.database, "benchmark");
+
+        // every rendered DDL is parsed back to the same columns
+        for target in RENDERED_TARGETS {
+            let sql = spec.render(target).unwrap();
+            let tables = parse_schema(&sql).unwrap();
+            assert_eq!(tables.len(), 1, "{}", sql);
+            let names: Vec<_> = tables[0].columns.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, ["id", "v", "ts"], "{}", sql);
+        }
+
+        let jb = &parse_schema(&spec.render("joinbase").unwrap()).unwrap()[0];
+        assert_eq!((jb.database.as_str(), jb.name.as_str()), ("benchmark", "t"));
+        assert_eq!(jb.columns[1].data_type, "Float32");
+        assert_eq!(jb.partitioning.as_ref().unwrap().method, "ymdh");
+
+        let ts_sql = spec.render("timescale").unwrap();
+        let ts = &parse_schema(&ts_sql).unwrap()[0];
+        assert_eq!(ts.columns[0].data_type, "bigint");
+        assert_eq!(ts.columns[1].data_type, "NUMERIC");
+        assert!(ts.columns[1].not_null && ts.columns[2].not_null && !ts.columns[0].not_null);
+        assert_eq!(ts.partitioning.as_ref().unwrap().args, ["ts", "id", "2"]);
+        assert!(ts_sql.contains("chunk_time_interval => interval '1 hour'"));
+        assert!(ts_sql.ends_with("CREATE INDEX ON t (id, ts DESC);\n"));
+
+        let pg = &parse_schema(&spec.render("postgresql").unwrap()).unwrap()[0];
+        assert_eq!(pg.columns[1].data_type, "real");
+        assert!(pg.partitioning.is_none());
+
+        let ch = &parse_schema(&spec.render("clickhouse").unwrap()).unwrap()[0];
+        assert_eq!(ch.partitioning.as_ref().unwrap().method, "toStartOfHour");
+
+        assert!(spec.render("other").is_err());
+        let three_days = "table = \"t\"\n[[columns]]\nname = \"ts\"\ntype = \"timestamp\"\n[partition]\ntime_column = \"ts\"\ninterval = \"3d\"\n";
+        let spec = SchemaSpec::parse(three_days).unwrap();
+        assert!(spec.render("joinbase").is_err());
+        assert!(!spec
+            .render("timescale")
+            .unwrap()
+            .contains("chunk_time_interval"));
+        assert!(SchemaSpec::parse(&format!(
+            "{}[targets.joinbase]\nchunk_time_interval = \"1h\"\n",
+            three_days
+        ))
+        .is_err());
+        assert!(spec
+            .render("clickhouse")
+            .unwrap()
+            .contains("PARTITION BY toStartOfInterval(ts, INTERVAL 3 day)"));
+
+        let with_override = format!(
+            "{}[targets.joinbase]\npartition_by = \"yyyymmdd3(ts)\"\n",
+            three_days
+        );
+        assert!(SchemaSpec::parse(&with_override)
+            .unwrap()
+            .render("joinbase")
+            .unwrap()
+            .contains("PARTITION BY yyyymmdd3(ts);"));
+
+        assert!(matches!(
+            SchemaSpec::parse("table = \"t\"\ncolumns = []"),
+            Err(OidbsError::InvalidDdl(_))
+        ));
+        assert!(
+            SchemaSpec::parse("table = \"t\"\n[[columns]]\nname = \"a\"\ntype = \"int128\"")
+                .is_err()
+        );
+        assert!(SchemaSpec::parse(&three_days.replace("3d", "3w")).is_err());
+        assert!(SchemaSpec::parse(&format!("{}[targets.other]\n", three_days)).is_err());
+    }
+}
@@ -0,0 +1,494 @@
+//! A minimal MQTT 3.1.1 broker stand-in, which accepts connections and counts the published messages
+//! without routing them to any subscriber.
+//!
+//! It measures how fast the client host can publish, without any server side bottleneck,
+//! and serves as the local MQTT endpoint of tests.
+
+use crate::{bench::soak::parse_duration, error::OidbsError};
+use clap::Args;
+use comfy_table::{Row, Table};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// the interval to check the stop flag while waiting for connections or data
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Args, Debug)]
+pub struct Sink {
+    /// the address to listen on for MQTT clients
+    #[clap(short, long, default_value_t = String::from("127.0.0.1:1883"))]
+    pub(crate) listen: String,
+
+    /// to acknowledge QoS 1 and QoS 2 messages(PUBACK, PUBREC and PUBCOMP), otherwise all messages are only counted
+    #[clap(short, long)]
+    pub(crate) ack: bool,
+
+    /// to stop after the duration, e.g. `30s`, `10m`. Units are `s`, `m`, `h` and `d`
+    #[clap(short, long)]
+    pub(crate) duration: Option<String>,
+
+    /// to stop once all clients disconnected, after the first one connected
+    #[clap(short, long)]
+    pub(crate) until_disconnected: bool,
+
+    /// the interval in seconds to report the receiving rates
+    #[clap(short, long, default_value_t = 1)]
+    pub(crate) interval_secs: u64,
+}
+
+/// the counters shared by all connections
+#[derive(Debug, Default)]
+struct Counters {
+    connections: AtomicU64,
+    active: AtomicU64,
+    messages: AtomicU64,
+    payload_bytes: AtomicU64,
+    wire_bytes: AtomicU64,
+    first_message: OnceLock<Instant>,
+    /// the time of the last message since the first one, in nanoseconds
+    last_message_ns: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self) -> SinkStats {
+        SinkStats {
+            connections: self.connections.load(Ordering::Relaxed),
+            active: self.active.load(Ordering::Relaxed),
+            messages: self.messages.load(Ordering::Relaxed),
+            payload_bytes: self.payload_bytes.load(Ordering::Relaxed),
+            wire_bytes: self.wire_bytes.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.last_message_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// the totals received by the sink
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SinkStats {
+    pub connections: u64,
+    /// the connections not closed yet
+    pub active: u64,
+    /// the number of PUBLISH packets
+    pub messages: u64,
+    /// the bytes of the payloads of all messages
+    pub payload_bytes: u64,
+    /// the bytes of all packets
+    pub wire_bytes: u64,
+    /// the time from the first message to the last one
+    pub busy: Duration,
+}
+
+impl SinkStats {
+    pub fn messages_per_sec(&self) -> f64 {
+        rate(self.messages, self.busy)
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        rate(self.payload_bytes, self.busy)
+    }
+}
+
+fn rate(n: u64, time: Duration) -> f64 {
+    if time.is_zero() {
+        0.0
+    } else {
+        n as f64 / time.as_secs_f64()
+    }
+}
+
+/// A running sink, which serves every connection in its own thread until stopped.
+pub struct SinkServer {
+    addr: SocketAddr,
+    counters: Arc<Counters>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl SinkServer {
+    /// Listens on `addr` and starts accepting clients, port 0 picks a free port.
+    pub fn start(addr: &str, ack: bool) -> Result<Self, OidbsError> {
+        let listener = TcpListener::bind(addr).map_err(|e| OidbsError::file(addr, e))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let counters = Arc::new(Counters::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (counters, stop) = (counters.clone(), stop.clone());
+            thread::spawn(move || accept_loop(listener, ack, counters, stop))
+        };
+        Ok(Self {
+            addr,
+            counters,
+            stop,
+            handle,
+        })
+    }
+
+    /// the address listened on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// the totals received so far
+    pub fn stats(&self) -> SinkStats {
+        self.counters.snapshot()
+    }
+
+    /// Closes all connections and returns the totals.
+    pub fn stop(self) -> Result<SinkStats, OidbsError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle
+            .join()
+            .map_err(|e| OidbsError::panicked("sink", e))?;
+        Ok(self.counters.snapshot())
+    }
+}
+
+fn accept_loop(listener: TcpListener, ack: bool, counters: Arc<Counters>, stop: Arc<AtomicBool>) {
+    let mut handles = vec![];
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                log::debug!("[sink] client {} connected", peer);
+                counters.connections.fetch_add(1, Ordering::Relaxed);
+                counters.active.fetch_add(1, Ordering::Relaxed);
+                let (counters, stop) = (counters.clone(), stop.clone());
+                handles.push(thread::spawn(move || {
+                    if let Err(e) = serve(stream, ack, &counters, &stop) {
+                        log::warn!("[sink] connection of {} failed: {}", peer, e);
+                    }
+                    counters.active.fetch_sub(1, Ordering::Relaxed);
+                }));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => log::warn!("[sink] failed to accept: {}", e),
+        }
+        handles.retain(|h| !h.is_finished());
+    }
+    for h in handles {
+        let _ = h.join();
+    }
+}
+
+/// Serves one client until it disconnects or the sink stops.
+fn serve(
+    mut stream: TcpStream,
+    ack: bool,
+    counters: &Counters,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let (mut start, mut end) = (0, 0);
+    let mut out = vec![];
+    loop {
+        let (mut messages, mut payload_bytes) = (0u64, 0u64);
+        let mut disconnected = false;
+        while let Some((header, offset, len)) = fixed_header(&buf[start..end])? {
+            if end - start < offset + len {
+                break;
+            }
+            let body = &buf[start + offset..start + offset + len];
+            match header >> 4 {
+                // CONNECT
+                1 => out.extend_from_slice(&[0x20, 0x02, 0x00, 0x00]),
+                // PUBLISH
+                3 => {
+                    let qos = (header >> 1) & 0x03;
+                    let topic_len = u16_at(body, 0)? as usize;
+                    let pid_len = if qos > 0 { 2 } else { 0 };
+                    let payload_len = len
+                        .checked_sub(2 + topic_len + pid_len)
+                        .ok_or_else(|| malformed("PUBLISH"))?;
+                    messages += 1;
+                    payload_bytes += payload_len as u64;
+                    if ack && qos > 0 {
+                        let pid = &body[2 + topic_len..4 + topic_len];
+                        let kind = if qos == 1 { 0x40 } else { 0x50 };
+                        out.extend_from_slice(&[kind, 0x02, pid[0], pid[1]]);
+                    }
+                }
+                // PUBREL
+                6 if ack => out.extend_from_slice(&[0x70, 0x02, u8_at(body, 0)?, u8_at(body, 1)?]),
+                // SUBSCRIBE, no message is routed to subscribers, so every filter is refused
+                8 => {
+                    let mut rest = body.get(2..).ok_or_else(|| malformed("SUBSCRIBE"))?;
+                    let mut codes = vec![];
+                    while !rest.is_empty() {
+                        let n = u16_at(rest, 0)? as usize;
+                        rest = rest.get(n + 3..).ok_or_else(|| malformed("SUBSCRIBE"))?;
+                        codes.push(0x80);
+                    }
+                    let mut suback = body[..2].to_vec();
+                    suback.extend(codes);
+                    write_packet(&mut out, 0x90, &suback);
+                }
+                // UNSUBSCRIBE
+                10 => out.extend_from_slice(&[0xb0, 0x02, u8_at(body, 0)?, u8_at(body, 1)?]),
+                // PINGREQ
+                12 => out.extend_from_slice(&[0xd0, 0x00]),
+                // DISCONNECT
+                14 => disconnected = true,
+                _ => {}
+            }
+            start += offset + len;
+        }
+        if messages > 0 {
+            let first = *counters.first_message.get_or_init(Instant::now);
+            counters.messages.fetch_add(messages, Ordering::Relaxed);
+            counters
+                .payload_bytes
+                .fetch_add(payload_bytes, Ordering::Relaxed);
+            counters
+                .last_message_ns
+                .fetch_max(first.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+        if !out.is_empty() {
+            stream.write_all(&out)?;
+            out.clear();
+        }
+        if disconnected || stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        // keeps the incomplete packet at the start, grows for the packets larger than the buffer
+        buf.copy_within(start..end, 0);
+        end -= start;
+        start = 0;
+        if end == buf.len() {
+            buf.resize(buf.len() * 2, 0);
+        }
+        match stream.read(&mut buf[end..]) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                end += n;
+                counters.wire_bytes.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// the fixed header at the start of `buf`: the first byte, the length of the fixed header
+/// and the remaining length. `None` if the fixed header is incomplete.
+fn fixed_header(buf: &[u8]) -> io::Result<Option<(u8, usize, usize)>> {
+    let header = match buf.first() {
+        Some(h) => *h,
+        None => return Ok(None),
+    };
+    let mut len = 0usize;
+    for i in 0..4 {
+        match buf.get(1 + i) {
+            Some(b) => {
+                len |= ((b & 0x7f) as usize) << (7 * i);
+                if b & 0x80 == 0 {
+                    return Ok(Some((header, 2 + i, len)));
+                }
+            }
+            None => return Ok(None),
+        }
+    }
+    Err(malformed("remaining length"))
+}
+
+fn write_packet(out: &mut Vec<u8>, header: u8, body: &[u8]) {
+    out.push(header);
+    let mut len = body.len();
+    loop {
+        let b = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            out.push(b);
+            break;
+        }
+        out.push(b | 0x80);
+    }
+    out.extend_from_slice(body);
+}
+
+fn u8_at(body: &[u8], i: usize) -> io::Result<u8> {
+    body.get(i).copied().ok_or_else(|| malformed("packet"))
+}
+
+fn u16_at(body: &[u8], i: usize) -> io::Result<u16> {
+    Ok(u16::from_be_bytes([u8_at(body, i)?, u8_at(body, i + 1)?]))
+}
+
+fn malformed(what: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("malformed {}", what))
+}
+
+/// the result of running the sink
+#[derive(Debug, Clone)]
+pub struct SinkResult {
+    pub stats: SinkStats,
+    pub elapsed: Duration,
+}
+
+pub struct SinkRunner {
+    listen: String,
+    ack: bool,
+    duration: Option<Duration>,
+    until_disconnected: bool,
+    interval: Duration,
+}
+
+impl SinkRunner {
+    pub fn new(sink: Sink) -> Result<Self, OidbsError> {
+        if sink.interval_secs == 0 {
+            return Err(OidbsError::InvalidArgs("interval_secs".into()));
+        }
+        Ok(Self {
+            listen: sink.listen,
+            ack: sink.ack,
+            duration: sink.duration.as_deref().map(parse_duration).transpose()?,
+            until_disconnected: sink.until_disconnected,
+            interval: Duration::from_secs(sink.interval_secs),
+        })
+    }
+
+    /// Runs the sink until the duration elapses or all clients disconnected,
+    /// reports the rates of every interval and the totals.
+    pub fn run(self) -> Result<SinkResult, OidbsError> {
+        let server = SinkServer::start(&self.listen, self.ack)?;
+        println!(
+            "[sink] listening on {}, QoS 1/2 acks: {}",
+            server.local_addr(),
+            if self.ack { "on" } else { "off" }
+        );
+        let ts = Instant::now();
+        let mut last = server.stats();
+        let mut next = self.interval;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let elapsed = ts.elapsed();
+            let stats = server.stats();
+            if elapsed >= next {
+                let time = elapsed - (next - self.interval);
+                println!(
+                    "[sink] {:>8.1}s  clients: {}, {:.0} messages/s, {:.2} MiB/s",
+                    elapsed.as_secs_f64(),
+                    stats.active,
+                    rate(stats.messages - last.messages, time),
+                    rate(stats.payload_bytes - last.payload_bytes, time) / (1024.0 * 1024.0)
+                );
+                last = stats;
+                next = elapsed + self.interval;
+            }
+            if self.duration.is_some_and(|d| elapsed >= d)
+                || (self.until_disconnected && stats.connections > 0 && stats.active == 0)
+            {
+                break;
+            }
+        }
+        let result = SinkResult {
+            stats: server.stop()?,
+            elapsed: ts.elapsed(),
+        };
+        print_summary(&result);
+        Ok(result)
+    }
+}
+
+fn print_summary(result: &SinkResult) {
+    let s = &result.stats;
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(Row::from(vec![
+        "Clients",
+        "Messages",
+        "Payload Bytes",
+        "Wire Bytes",
+        "Busy Time",
+        "Messages/s",
+        "MiB/s",
+    ]));
+    table.add_row(vec![
+        s.connections.to_string(),
+        s.messages.to_string(),
+        s.payload_bytes.to_string(),
+        s.wire_bytes.to_string(),
+        format!("{:.3}s", s.busy.as_secs_f64()),
+        format!("{:.0}", s.messages_per_sec()),
+        format!("{:.2}", s.bytes_per_sec() / (1024.0 * 1024.0)),
+    ]);
+    println!("{}", table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_packet, SinkServer};
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        thread,
+        time::Duration,
+    };
+
+    fn publish(qos: u8, pid: u16, payload: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x00, 0x01, b't'];
+        if qos > 0 {
+            body.extend(pid.to_be_bytes());
+        }
+        body.extend(payload);
+        let mut p = vec![];
+        write_packet(&mut p, 0x30 | (qos << 1), &body);
+        p
+    }
+
+    fn read_n(s: &mut TcpStream, n: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; n];
+        s.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_sink() {
+        let server = SinkServer::start("127.0.0.1:0", true).unwrap();
+        let mut s = TcpStream::connect(server.local_addr()).unwrap();
+        let mut connect = vec![];
+        write_packet(&mut connect, 0x10, b"\x00\x04MQTT\x04\x02\x00\x3c\x00\x01c");
+        s.write_all(&connect).unwrap();
+        assert_eq!(read_n(&mut s, 4), [0x20, 0x02, 0x00, 0x00]);
+
+        // a large message is split across reads and grows the buffer
+        let big = vec![b'x'; 200 * 1024];
+        let mut packets = publish(0, 0, b"a,1").repeat(3);
+        packets.extend(publish(0, 0, &big));
+        packets.extend(publish(1, 7, b"b,2"));
+        let (head, tail) = packets.split_at(5);
+        s.write_all(head).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        s.write_all(tail).unwrap();
+        assert_eq!(read_n(&mut s, 4), [0x40, 0x02, 0x00, 0x07]);
+
+        s.write_all(&publish(2, 9, b"c")).unwrap();
+        assert_eq!(read_n(&mut s, 4), [0x50, 0x02, 0x00, 0x09]);
+        s.write_all(&[0x62, 0x02, 0x00, 0x09, 0xc0, 0x00]).unwrap();
+        assert_eq!(read_n(&mut s, 6), [0x70, 0x02, 0x00, 0x09, 0xd0, 0x00]);
+        s.write_all(&[0xe0, 0x00]).unwrap();
+        assert_eq!(s.read(&mut [0u8; 1]).unwrap(), 0);
+
+        let stats = server.stop().unwrap();
+        assert_eq!(stats.connections, 1);
+        assert_eq!(stats.active, 0);
+        assert_eq!(stats.messages, 6);
+        assert_eq!(stats.payload_bytes, 3 * 3 + big.len() as u64 + 3 + 1);
+        assert!(stats.wire_bytes > stats.payload_bytes);
+    }
+}
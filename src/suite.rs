@@ -0,0 +1,701 @@
+//! `oidbs run <suite.toml>`, which runs a whole benchmark declared in one reviewable file:
+//! gen, then import and bench for every target and model, with one consolidated result bundle.
+//!
+//! ```toml
+//! name = "pstations-nightly"
+//! models = ["pstations"]
+//!
+//! [[targets]]
+//! kind = "joinbase"
+//! ib_srv_part_pg = "abc:abc@127.0.0.1:5433"
+//! ib_srv_part_mqtt = "abc:abc@127.0.0.1:1883"
+//!
+//! [[targets]]
+//! kind = "timescale"
+//! pg_srv_part = "postgres:postgres@127.0.0.1:5432"
+//! database = "tsbench"
+//!
+//! [gen]
+//! workers = 4
+//! interval_per_worker_sec = 3600
+//! model_parameters = { num_stations = 100 }
+//!
+//! [import]
+//! num_rows_in_batch = 100
+//!
+//! [[bench]]
+//! measurement_mode = "latency"
+//! run_times = 5
+//!
+//! [[bench]]
+//! measurement_mode = "concurrency"
+//! threads = "1,2,4,8,16"
+//! ```
+//!
+//! Every setting not given takes the default of the same option of `gen`, `import` or `bench`.
+//! Without `[gen]` the dataset is read from `dataset_dir`, and without `[import]` the targets
+//! are expected to hold the dataset already.
+//!
+//! The bundle contains the suite file, the generated dataset(unless `dataset_dir` is given),
+//! the result document of every bench run in `results/` and `summary.json` of all phases,
+//! which is rewritten after every phase, so a failed run still leaves its partial results.
+
+use crate::{
+    bench::{soak::parse_duration, Bench, MeasurementMode, QueryRequestor},
+    error::OidbsError,
+    gen::{Gen, Generator},
+    import::{Import, Importer, StorageFootprint},
+    model::Model,
+    profile::{Profiles, DEFAULT_DATABASE},
+    results::HostInfo,
+    target,
+};
+use clap::Args;
+use comfy_table::{Row, Table};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
+
+pub const SUITE_FILE: &str = "suite.toml";
+pub const SUMMARY_FILE: &str = "summary.json";
+
+#[derive(Args, Debug)]
+pub struct Run {
+    /// the suite file in TOML, which declares the targets, models and the settings of every phase
+    pub(crate) suite: String,
+
+    /// the directory of the result bundle, `<suite name>-<start time>` in the current directory if not given
+    #[clap(short, long)]
+    pub(crate) output_dir: Option<String>,
+
+    /// to only print the phases of the suite without running them
+    #[clap(long)]
+    pub(crate) dry_run: bool,
+
+    /// the connection profiles, by which the targets are resolved
+    #[clap(skip)]
+    pub(crate) profiles: Profiles,
+}
+
+builder_methods!(Run { profiles: Profiles });
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuiteSpec {
+    pub name: String,
+    pub models: Vec<String>,
+    pub targets: Vec<TargetSpec>,
+    /// the dataset to import, or the directory to generate it into with `[gen]`
+    pub dataset_dir: Option<String>,
+    pub gen: Option<GenSpec>,
+    pub import: Option<ImportSpec>,
+    #[serde(default)]
+    pub bench: Vec<BenchSpec>,
+}
+
+/// one target to import into and bench, with the endpoints of its server
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetSpec {
+    /// a connection profile or a registered target, e.g. `joinbase` or `timescale`.
+    /// The endpoints below are only used without a profile
+    pub kind: String,
+    #[serde(default = "default_ib_srv_part_mqtt")]
+    pub ib_srv_part_mqtt: String,
+    #[serde(default = "default_ib_srv_part_pg")]
+    pub ib_srv_part_pg: String,
+    #[serde(default = "default_pg_srv_part")]
+    pub pg_srv_part: String,
+    #[serde(default = "default_database")]
+    pub database: String,
+    pub size_sql: Option<String>,
+    pub server_data_dir: Option<String>,
+}
+
+fn default_ib_srv_part_mqtt() -> String {
+    "abc:abc@127.0.0.1:1883".into()
+}
+
+fn default_ib_srv_part_pg() -> String {
+    "abc:abc@127.0.0.1:5433".into()
+}
+
+fn default_pg_srv_part() -> String {
+    "postgres:postgres@127.0.0.1:5432".into()
+}
+
+fn default_database() -> String {
+    DEFAULT_DATABASE.into()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenSpec {
+    pub workers: u32,
+    pub timestamp_start: String,
+    pub interval_per_worker_sec: u32,
+    pub step_sec: u32,
+    pub format: String,
+    pub out_of_order: bool,
+    pub model_parameters: Map<String, Value>,
+}
+
+impl Default for GenSpec {
+    fn default() -> Self {
+        // the CLI defaults are checked by the tests of the builders
+        let gen = Gen::new("").expect("CLI defaults of gen");
+        Self {
+            workers: gen.workers,
+            timestamp_start: gen.timestamp_start,
+            interval_per_worker_sec: gen.interval_per_worker_sec,
+            step_sec: gen.step_sec,
+            format: gen.format,
+            out_of_order: gen.out_of_order,
+            model_parameters: serde_json::from_str(&gen.model_parameters).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImportSpec {
+    pub import_data_only: bool,
+    pub num_workers_timescale: i32,
+    pub num_rows_in_batch: i32,
+    pub skip_verify: bool,
+    pub verify_columns: Option<String>,
+    pub verify_wait_secs: u64,
+}
+
+impl Default for ImportSpec {
+    fn default() -> Self {
+        let import = Import::new("").expect("CLI defaults of import");
+        Self {
+            import_data_only: import.import_data_only,
+            num_workers_timescale: import.num_workers_timescale,
+            num_rows_in_batch: import.num_rows_in_batch,
+            skip_verify: import.skip_verify,
+            verify_columns: import.verify_columns,
+            verify_wait_secs: import.verify_wait_secs,
+        }
+    }
+}
+
+/// one bench run against every target and model
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BenchSpec {
+    pub measurement_mode: String,
+    pub run_times: u32,
+    pub warmup_times: u32,
+    pub num_concurrent_threads: usize,
+    pub threads: Option<String>,
+    pub knee_min_gain: f64,
+    pub query_timeout_ms: u64,
+    pub max_error_rate: f64,
+    pub seed: u64,
+    pub duration: Option<String>,
+    pub interval_secs: u64,
+    pub drift_threshold: f64,
+}
+
+impl Default for BenchSpec {
+    fn default() -> Self {
+        let bench = Bench::new("joinbase", "pstations").expect("CLI defaults of bench");
+        Self {
+            measurement_mode: bench.measurement_mode,
+            run_times: bench.run_times,
+            warmup_times: bench.warmup_times,
+            num_concurrent_threads: bench.num_concurrent_threads,
+            threads: bench.threads,
+            knee_min_gain: bench.knee_min_gain,
+            query_timeout_ms: bench.query_timeout_ms,
+            max_error_rate: bench.max_error_rate,
+            seed: bench.seed,
+            duration: bench.duration,
+            interval_secs: bench.interval_secs,
+            drift_threshold: bench.drift_threshold,
+        }
+    }
+}
+
+/// one step of the suite, the indices are into the targets and the bench runs of the suite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Phase {
+    Gen,
+    Import {
+        target: usize,
+        model: String,
+    },
+    Bench {
+        target: usize,
+        model: String,
+        bench: usize,
+    },
+}
+
+impl SuiteSpec {
+    pub fn parse(s: &str) -> Result<Self, OidbsError> {
+        let spec: SuiteSpec =
+            toml::from_str(s).map_err(|e| OidbsError::InvalidArgs(format!("suite({})", e)))?;
+        let invalid = |msg: String| Err(OidbsError::InvalidArgs(format!("suite({})", msg)));
+        if spec.name.is_empty() || spec.name.contains(['/', '\\']) {
+            return invalid(format!("name '{}'", spec.name));
+        }
+        if spec.models.is_empty() || spec.targets.is_empty() {
+            return invalid("no models or no targets".into());
+        }
+        if spec.gen.is_none() && spec.dataset_dir.is_none() {
+            return invalid("either [gen] or dataset_dir is required".into());
+        }
+        for b in &spec.bench {
+            MeasurementMode::from_str(&b.measurement_mode)?;
+            if let Some(d) = &b.duration {
+                parse_duration(d)?;
+            }
+        }
+        Ok(spec)
+    }
+
+    /// the phases in the order to run: gen, then the import and bench runs target by target
+    pub fn phases(&self) -> Vec<Phase> {
+        let mut rt = vec![];
+        if self.gen.is_some() {
+            rt.push(Phase::Gen);
+        }
+        for target in 0..self.targets.len() {
+            for model in &self.models {
+                if self.import.is_some() {
+                    rt.push(Phase::Import {
+                        target,
+                        model: model.clone(),
+                    });
+                }
+                for bench in 0..self.bench.len() {
+                    rt.push(Phase::Bench {
+                        target,
+                        model: model.clone(),
+                        bench,
+                    });
+                }
+            }
+        }
+        rt
+    }
+}
+
+/// the summary of a suite run, `summary.json` in the bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteResult {
+    pub oidbs_version: String,
+    pub name: String,
+    /// the start time of the run, in RFC 3339
+    pub timestamp: String,
+    pub host: HostInfo,
+    pub phases: Vec<PhaseResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseResult {
+    /// `gen`, `import` or `bench`
+    pub phase: String,
+    pub model: Option<String>,
+    pub target: Option<String>,
+    pub measurement_mode: Option<String>,
+    pub elapsed_ms: u64,
+    /// the error of a failed phase, the suite stops at the first failed phase
+    pub error: Option<String>,
+    /// the storage footprint after importing
+    pub storage: Option<StorageFootprint>,
+    /// the result document of a bench run, relative to the bundle
+    pub result_file: Option<String>,
+}
+
+pub struct SuiteRunner {
+    spec: SuiteSpec,
+    /// the content of the suite file, which is copied into the bundle
+    suite: String,
+    output_dir: PathBuf,
+    dataset_dir: String,
+    dry_run: bool,
+    models: Vec<Model>,
+    profiles: Profiles,
+}
+
+impl SuiteRunner {
+    pub fn new(run: Run, models: Vec<Model>) -> Result<Self, OidbsError> {
+        let suite = fs::read_to_string(&run.suite).map_err(|e| OidbsError::file(&run.suite, e))?;
+        let spec = SuiteSpec::parse(&suite)?;
+        let registered = target::names();
+        for t in &spec.targets {
+            let (kind, _) = run.profiles.resolve(&t.kind);
+            if !registered.contains(&kind.as_str()) {
+                return Err(OidbsError::InvalidArgs(format!(
+                    "target {}, options included of connection profiles and {}",
+                    t.kind,
+                    registered.join(", ")
+                )));
+            }
+        }
+        let mut suite_models = vec![];
+        for name in &spec.models {
+            match models.iter().find(|m| &m.name == name) {
+                Some(m) if spec.gen.is_some() && !m.has_completed => {
+                    return Err(OidbsError::InvalidArgs(format!(
+                        "model {} without generator support in a suite with [gen], give dataset_dir instead",
+                        name
+                    )))
+                }
+                Some(m) => suite_models.push(m.clone()),
+                None => return Err(OidbsError::UnimplementedModel(name.clone())),
+            }
+        }
+
+        let output_dir = PathBuf::from(run.output_dir.unwrap_or_else(|| {
+            format!(
+                "{}-{}",
+                spec.name,
+                chrono::Local::now().format("%Y%m%dT%H%M%S")
+            )
+        }));
+        if output_dir.join(SUMMARY_FILE).exists() {
+            return Err(OidbsError::InvalidArgs(format!(
+                "output dir {}, which holds the results of another run",
+                output_dir.display()
+            )));
+        }
+        let dataset_dir = match &spec.dataset_dir {
+            Some(dir) => dir.clone(),
+            None => output_dir.join("dataset").display().to_string(),
+        };
+        Ok(Self {
+            spec,
+            suite,
+            output_dir,
+            dataset_dir,
+            dry_run: run.dry_run,
+            models: suite_models,
+            profiles: run.profiles,
+        })
+    }
+
+    fn describe(&self, phase: &Phase) -> String {
+        match phase {
+            Phase::Gen => format!(
+                "gen {} into {}",
+                self.spec.models.join(", "),
+                self.dataset_dir
+            ),
+            Phase::Import { target, model } => format!(
+                "import {} from {} into {}",
+                model, self.dataset_dir, self.spec.targets[*target].kind
+            ),
+            Phase::Bench {
+                target,
+                model,
+                bench,
+            } => format!(
+                "bench #{} {} of {} on {}",
+                bench + 1,
+                self.spec.bench[*bench].measurement_mode,
+                model,
+                self.spec.targets[*target].kind
+            ),
+        }
+    }
+
+    pub fn run(self) -> Result<(), OidbsError> {
+        let phases = self.spec.phases();
+        if self.dry_run {
+            for (i, phase) in phases.iter().enumerate() {
+                println!("{}. {}", i + 1, self.describe(phase));
+            }
+            return Ok(());
+        }
+
+        let results_dir = self.output_dir.join("results");
+        fs::create_dir_all(&results_dir).map_err(|e| OidbsError::file(&results_dir, e))?;
+        fs::create_dir_all(&self.dataset_dir)
+            .map_err(|e| OidbsError::file(&self.dataset_dir, e))?;
+        let suite_file = self.output_dir.join(SUITE_FILE);
+        fs::write(&suite_file, &self.suite).map_err(|e| OidbsError::file(&suite_file, e))?;
+        let mut summary = SuiteResult {
+            oidbs_version: env!("CARGO_PKG_VERSION").to_string(),
+            name: self.spec.name.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            host: HostInfo::collect(),
+            phases: vec![],
+        };
+        let mut failure = None;
+        for (i, phase) in phases.iter().enumerate() {
+            println!("[run] {}/{}: {}", i + 1, phases.len(), self.describe(phase));
+            let t = Instant::now();
+            let (mut result, ret) = self.run_phase(i, phase);
+            result.elapsed_ms = t.elapsed().as_millis() as u64;
+            if let Err(e) = &ret {
+                result.error = Some(e.to_string());
+            }
+            summary.phases.push(result);
+            self.write_summary(&summary)?;
+            if let Err(e) = ret {
+                let phase = format!("phase {}({})", i + 1, self.describe(phase));
+                failure = Some(OidbsError::Context(phase, Box::new(e)));
+                break;
+            }
+        }
+
+        print_summary(&summary);
+        println!("result bundle written to {}", self.output_dir.display());
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn run_phase(&self, i: usize, phase: &Phase) -> (PhaseResult, Result<(), OidbsError>) {
+        let mut result = PhaseResult {
+            phase: String::new(),
+            model: None,
+            target: None,
+            measurement_mode: None,
+            elapsed_ms: 0,
+            error: None,
+            storage: None,
+            result_file: None,
+        };
+        let ret = match phase {
+            Phase::Gen => {
+                result.phase = "gen".into();
+                self.gen()
+            }
+            Phase::Import { target, model } => {
+                result.phase = "import".into();
+                result.model = Some(model.clone());
+                result.target = Some(self.spec.targets[*target].kind.clone());
+                self.import(&self.spec.targets[*target], model)
+                    .map(|storage| result.storage = Some(storage))
+            }
+            Phase::Bench {
+                target,
+                model,
+                bench,
+            } => {
+                let (t, b) = (&self.spec.targets[*target], &self.spec.bench[*bench]);
+                let file = format!(
+                    "results/{:02}-{}-{}-{}.json",
+                    i + 1,
+                    t.kind,
+                    model,
+                    b.measurement_mode
+                );
+                result.phase = "bench".into();
+                result.model = Some(model.clone());
+                result.target = Some(t.kind.clone());
+                result.measurement_mode = Some(b.measurement_mode.clone());
+                let output = self.output_dir.join(&file);
+                let ret = self.bench(t, model, b, &output);
+                // the result document is written even if the run fails on its error rate
+                if output.exists() {
+                    result.result_file = Some(file);
+                }
+                ret
+            }
+        };
+        (result, ret)
+    }
+
+    fn gen(&self) -> Result<(), OidbsError> {
+        let spec = self.spec.gen.clone().unwrap_or_default();
+        let gen = Gen::new(&self.dataset_dir)?
+            .workers(spec.workers)
+            .timestamp_start(spec.timestamp_start)
+            .interval_per_worker_sec(spec.interval_per_worker_sec)
+            .step_sec(spec.step_sec)
+            .format(spec.format)
+            .out_of_order(spec.out_of_order)
+            .model_parameters(serde_json::to_string(&spec.model_parameters)?);
+        Generator::new(gen, self.models.clone())?.run()?;
+        Ok(())
+    }
+
+    fn import(&self, target: &TargetSpec, model: &str) -> Result<StorageFootprint, OidbsError> {
+        let spec = self.spec.import.clone().unwrap_or_default();
+        let import = Import::new(&self.dataset_dir)?
+            .ib_srv_part_mqtt(target.ib_srv_part_mqtt.clone())
+            .ib_srv_part_pg(target.ib_srv_part_pg.clone())
+            .pg_srv_part(target.pg_srv_part.clone())
+            .database(target.database.clone())
+            .target_kind(target.kind.clone())
+            .model_name(model)
+            .import_data_only(spec.import_data_only)
+            .num_workers_timescale(spec.num_workers_timescale)
+            .num_rows_in_batch(spec.num_rows_in_batch)
+            .skip_verify(spec.skip_verify)
+            .verify_wait_secs(spec.verify_wait_secs)
+            .profiles(self.profiles.clone());
+        let import = Import {
+            size_sql: target.size_sql.clone(),
+            server_data_dir: target.server_data_dir.clone(),
+            verify_columns: spec.verify_columns,
+            ..import
+        };
+        Ok(Importer::new(import, self.models.clone())?.run()?.storage)
+    }
+
+    fn bench(
+        &self,
+        target: &TargetSpec,
+        model: &str,
+        spec: &BenchSpec,
+        output: &Path,
+    ) -> Result<(), OidbsError> {
+        let bench = Bench::new(&target.kind, model)?
+            .ib_srv_part_pg(target.ib_srv_part_pg.clone())
+            .pg_srv_part(target.pg_srv_part.clone())
+            .database(target.database.clone())
+            .run_times(spec.run_times)
+            .measurement_mode(spec.measurement_mode.clone())
+            .warmup_times(spec.warmup_times)
+            .num_concurrent_threads(spec.num_concurrent_threads)
+            .query_timeout_ms(spec.query_timeout_ms)
+            .max_error_rate(spec.max_error_rate)
+            .seed(spec.seed)
+            .knee_min_gain(spec.knee_min_gain)
+            .interval_secs(spec.interval_secs)
+            .drift_threshold(spec.drift_threshold)
+            .output(output.display().to_string())
+            .dataset_dir(self.dataset_dir.clone())
+            .profiles(self.profiles.clone());
+        let bench = Bench {
+            threads: spec.threads.clone(),
+            duration: spec.duration.clone(),
+            ..bench
+        };
+        QueryRequestor::new(bench, self.models.clone())?.run()?;
+        Ok(())
+    }
+
+    fn write_summary(&self, summary: &SuiteResult) -> Result<(), OidbsError> {
+        let path = self.output_dir.join(SUMMARY_FILE);
+        let file = fs::File::create(&path).map_err(|e| OidbsError::file(&path, e))?;
+        serde_json::to_writer_pretty(file, summary)?;
+        Ok(())
+    }
+}
+
+fn print_summary(summary: &SuiteResult) {
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(Row::from(vec![
+        "No", "Phase", "Model", "Target", "Mode", "Time", "Status",
+    ]));
+    for (i, p) in summary.phases.iter().enumerate() {
+        table.add_row(vec![
+            (i + 1).to_string(),
+            p.phase.clone(),
+            p.model.clone().unwrap_or_default(),
+            p.target.clone().unwrap_or_default(),
+            p.measurement_mode.clone().unwrap_or_default(),
+            format!("{:.3}s", p.elapsed_ms as f64 / 1000.0),
+            p.error.clone().unwrap_or_else(|| "ok".into()),
+        ]);
+    }
+    println!("{}", table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Phase, SuiteSpec};
+
+    #[test]
+    fn test_parse_suite() {
+        let spec = SuiteSpec::parse(
+            r#"
+name = "nightly"
+models = ["pstations", "nyct_strip"]
+
+[[targets]]
+kind = "joinbase"
+
+[[targets]]
+kind = "timescale"
+pg_srv_part = "postgres:secret@10.0.0.2:5432"
+
+[gen]
+workers = 4
+model_parameters = { num_stations = 100 }
+
+[import]
+num_rows_in_batch = 100
+
+[[bench]]
+run_times = 5
+
+[[bench]]
+measurement_mode = "concurrency"
+threads = "1,2,4"
+"#,
+        )
+        .unwrap();
+        assert_eq!(spec.targets[0].ib_srv_part_pg, "abc:abc@127.0.0.1:5433");
+        assert_eq!(spec.targets[1].pg_srv_part, "postgres:secret@10.0.0.2:5432");
+        let gen = spec.gen.as_ref().unwrap();
+        assert_eq!((gen.workers, gen.step_sec), (4, 1));
+        assert_eq!(gen.model_parameters["num_stations"], 100);
+        let import = spec.import.as_ref().unwrap();
+        assert_eq!(
+            (import.num_rows_in_batch, import.verify_wait_secs),
+            (100, 60)
+        );
+        assert_eq!(spec.bench[0].measurement_mode, "latency");
+        assert_eq!(spec.bench[0].run_times, 5);
+        assert_eq!(spec.bench[1].run_times, 3);
+
+        // gen, then (import + 2 bench runs) for every model of every target
+        let phases = spec.phases();
+        assert_eq!(phases.len(), 1 + 2 * 2 * 3);
+        assert_eq!(phases[0], Phase::Gen);
+        assert_eq!(
+            phases[1],
+            Phase::Import {
+                target: 0,
+                model: "pstations".into()
+            }
+        );
+        assert_eq!(
+            phases[6],
+            Phase::Bench {
+                target: 0,
+                model: "nyct_strip".into(),
+                bench: 1
+            }
+        );
+        assert_eq!(
+            phases[7],
+            Phase::Import {
+                target: 1,
+                model: "pstations".into()
+            }
+        );
+
+        let minimal = "name = \"n\"\nmodels = [\"m\"]\ndataset_dir = \"/data\"\n[[targets]]\nkind = \"joinbase\"\n";
+        let spec = SuiteSpec::parse(minimal).unwrap();
+        assert!(spec.gen.is_none() && spec.import.is_none());
+        assert!(spec.phases().is_empty());
+
+        assert!(SuiteSpec::parse(&minimal.replace("dataset_dir = \"/data\"\n", "")).is_err());
+        assert!(SuiteSpec::parse(&minimal.replace("\"m\"", "")).is_err());
+        assert!(SuiteSpec::parse(&format!("{}unknown = 1\n", minimal)).is_err());
+        assert!(SuiteSpec::parse(&format!(
+            "{}[[bench]]\nmeasurement_mode = \"qps\"\n",
+            minimal
+        ))
+        .is_err());
+        assert!(SuiteSpec::parse(&format!("{}[[bench]]\nduration = \"1y\"\n", minimal)).is_err());
+    }
+}
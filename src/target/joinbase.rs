@@ -0,0 +1,262 @@
+//! JoinBase, which ingests via MQTT and is queried via the pg wire protocol.
+
+use super::{pgwire, Target, TargetConfig, TargetConnection};
+use crate::{
+    bench::dialect::Dialect,
+    error::{join_workers, OidbsError},
+    manifest,
+    model::Model,
+    mqtt_client::{self, client::Client, MqttOptions, QoS},
+    profile::ConnectionProfile,
+};
+use itertools::Itertools;
+use log::*;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+const PG_PORT: u16 = 5433;
+const MQTT_PORT: u16 = 1883;
+
+pub struct JoinBase {
+    pg: ConnectionProfile,
+    /// the connection string of `pg`, which contains the password
+    conninfo: String,
+    /// the MQTT endpoint, whose port is the MQTT one
+    broker: ConnectionProfile,
+    num_rows_in_batch: i32,
+    size_sql: Option<String>,
+}
+
+impl JoinBase {
+    pub fn create(config: &TargetConfig) -> Result<Box<dyn Target>, OidbsError> {
+        let pg = config.connection("joinbase", &config.ib_srv_part_pg)?;
+        // a profile gives both endpoints on the same host with the same credentials
+        let broker = match &config.profile {
+            Some(p) => ConnectionProfile {
+                port: Some(p.mqtt_port.unwrap_or(MQTT_PORT)),
+                ..p.clone()
+            },
+            None => ConnectionProfile::from_srv_part(
+                "joinbase",
+                &config.ib_srv_part_mqtt,
+                &config.database,
+            )?,
+        };
+        Ok(Box::new(JoinBase {
+            conninfo: pg.conninfo(PG_PORT, true)?,
+            pg,
+            broker,
+            num_rows_in_batch: config.num_rows_in_batch,
+            size_sql: config.size_sql.clone(),
+        }))
+    }
+
+    fn mqtt_options(&self) -> Result<MqttOptions, OidbsError> {
+        let mut options = MqttOptions::new(
+            "oidbs",
+            self.broker.host.clone(),
+            self.broker.port.unwrap_or(MQTT_PORT),
+        )?;
+        if let Some(user) = &self.broker.user {
+            options.set_credentials(user.clone(), self.broker.password()?.unwrap_or_default());
+        }
+        Ok(options)
+    }
+
+    /// the topic to publish the dataset of the model
+    fn topic(model: &Model) -> Result<String, OidbsError> {
+        let schema = model
+            .target_infos
+            .get("joinbase")
+            .ok_or_else(|| OidbsError::UnimplementedModel(model.name.clone()))?;
+        Ok(format!("/{}/{}", schema.database, schema.table))
+    }
+
+    fn data_files(model: &Model, data_dir: &str) -> Result<Vec<PathBuf>, OidbsError> {
+        let model_dir = data_dir.to_string() + "/" + &model.name;
+        debug!("model_dir: {}", model_dir);
+        manifest::data_files(model_dir)
+    }
+}
+
+impl Target for JoinBase {
+    fn name(&self) -> &'static str {
+        "joinbase"
+    }
+
+    fn connect(
+        &self,
+        query_timeout: Option<Duration>,
+    ) -> Result<Box<dyn TargetConnection>, OidbsError> {
+        // JoinBase does not support `statement_timeout`, so queries are canceled by the client
+        let conn = pgwire::PgWireConnection::connect(&self.conninfo, query_timeout, false)?;
+        Ok(Box::new(conn))
+    }
+
+    fn setup_schema(&self, model: &Model) -> Result<(), OidbsError> {
+        println!("[setup_schemas]to connect to joinbase server: {}", self.pg);
+        match model.target_infos.get(self.name()) {
+            Some(v) => pgwire::exec_batch(&self.conninfo, &v.schema),
+            None => {
+                debug!("no {} schema found for target: joinbase", model.name);
+                Ok(())
+            }
+        }
+    }
+
+    fn bulk_ingest(&self, model: &Model, data_dir: &str) -> Result<(), OidbsError> {
+        println!("-> import to: {}", self.broker);
+        let options = self.mqtt_options()?;
+        let topic = Self::topic(model)?;
+        let batch = self.num_rows_in_batch as usize;
+        thread::scope(|s| {
+            let handles: Vec<_> = Self::data_files(model, data_dir)?
+                .into_iter()
+                .map(|file_path| {
+                    println!("-> to import: {:?}", file_path.as_path());
+                    let opts = options.clone();
+                    let topic = topic.clone();
+                    let worker = format!("publishing {}", file_path.display());
+                    let h = s.spawn(move || -> Result<(), OidbsError> {
+                        let mut client = Client::new(opts)?;
+                        client.handshake()?;
+                        let reader = BufReader::new(
+                            File::open(&file_path).map_err(|e| OidbsError::file(&file_path, e))?,
+                        );
+                        for chunk in &reader.lines().chunks(batch) {
+                            let text = chunk.collect::<Result<Vec<_>, _>>()?.join("\n");
+                            match client.publish_bytes(topic.clone(), QoS::AtMostOnce, text.into()) {
+                                Err(e @ mqtt_client::Error::Io(_)) => return Err(e.into()),
+                                Err(e) => error!("publish failed, {}", e),
+                                Ok(()) => {}
+                            }
+                        }
+                        Ok(())
+                    });
+                    (worker, h)
+                })
+                .collect();
+            join_workers(handles)?;
+            Ok(())
+        })
+    }
+
+    /// Every file is replayed from its beginning when exhausted,
+    /// so the ingest load keeps constant for the whole run.
+    fn stream_ingest(
+        &self,
+        model: &Model,
+        data_dir: &str,
+        rate: u64,
+        stop: &AtomicBool,
+        ingested: &AtomicU64,
+    ) -> Result<(), OidbsError> {
+        let options = self.mqtt_options()?;
+        let topic = Self::topic(model)?;
+        let files = Self::data_files(model, data_dir)?;
+        if files.is_empty() || rate == 0 {
+            return Err(OidbsError::InvalidArgs("ingest rate or input_dir".into()));
+        }
+        let rate_per_file = rate as f64 / files.len() as f64;
+        let batch = self.num_rows_in_batch as usize;
+
+        thread::scope(|s| {
+            let handles: Vec<_> = files
+                .into_iter()
+                .map(|file_path| {
+                    let opts = options.clone();
+                    let topic = topic.clone();
+                    let worker = format!("publishing {}", file_path.display());
+                    let h = s.spawn(move || -> Result<(), OidbsError> {
+                        let mut client = Client::new(opts)?;
+                        client.handshake()?;
+                        let ts = Instant::now();
+                        let mut sent = 0u64;
+                        'replay: while !stop.load(Ordering::Relaxed) {
+                            let reader = BufReader::new(
+                                File::open(&file_path)
+                                    .map_err(|e| OidbsError::file(&file_path, e))?,
+                            );
+                            for chunk in &reader.lines().chunks(batch) {
+                                if stop.load(Ordering::Relaxed) {
+                                    break 'replay;
+                                }
+                                let rows = chunk.collect::<Result<Vec<_>, _>>()?;
+                                let n = rows.len() as u64;
+                                let text = rows.join("\n");
+                                match client.publish_bytes(
+                                    topic.clone(),
+                                    QoS::AtMostOnce,
+                                    text.into(),
+                                ) {
+                                    Err(e @ mqtt_client::Error::Io(_)) => return Err(e.into()),
+                                    Err(e) => error!("publish failed, {}", e),
+                                    Ok(()) => {
+                                        ingested.fetch_add(n, Ordering::Relaxed);
+                                    }
+                                }
+                                // paced by the attempted rows, so failed publishes are throttled too
+                                sent += n;
+                                let due = Duration::from_secs_f64(sent as f64 / rate_per_file);
+                                let elapsed = ts.elapsed();
+                                if due > elapsed {
+                                    thread::sleep(due - elapsed);
+                                }
+                            }
+                        }
+                        Ok(())
+                    });
+                    (worker, h)
+                })
+                .collect();
+            join_workers(handles)?;
+            Ok(())
+        })
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::JoinBase
+    }
+
+    fn fetch_server_version(&self) -> Option<String> {
+        pgwire::fetch_server_version(&self.conninfo)
+    }
+
+    fn qualified_table(&self, model: &Model) -> Result<String, OidbsError> {
+        let info = model
+            .target_infos
+            .get(self.name())
+            .ok_or_else(|| OidbsError::UnimplementedModel(model.name.clone()))?;
+        Ok(format!("{}.{}", info.database, info.table))
+    }
+
+    fn fetch_rows(&self, sql: &str) -> Result<Vec<Vec<Option<String>>>, OidbsError> {
+        pgwire::query_rows(&self.conninfo, sql)
+    }
+
+    /// JoinBase has no built-in size query, only the configured SQL is used.
+    fn storage_size(&self, model: &Model) -> Result<Option<u64>, OidbsError> {
+        match (&self.size_sql, model.target_infos.get(self.name())) {
+            (Some(sql), Some(info)) => {
+                pgwire::query_size(&self.conninfo, &super::render_size_sql(sql, info))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn teardown(&self, model: &Model) -> Result<(), OidbsError> {
+        match model.target_infos.get(self.name()) {
+            Some(v) => pgwire::exec_batch(
+                &self.conninfo,
+                &format!("drop table if exists {}.{}", v.database, v.table),
+            ),
+            None => Ok(()),
+        }
+    }
+}
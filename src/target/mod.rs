@@ -0,0 +1,267 @@
+//! Databases under benchmark.
+//!
+//! Every database is a [`Target`], which is found by its name through the registry.
+//! The core loops of `import` and `bench` only talk to the trait,
+//! so a new database can be added by implementing the trait and calling [`register`].
+
+use crate::{
+    bench::dialect::Dialect,
+    error::OidbsError,
+    model::{Model, TargetInfo},
+    profile::{ConnectionProfile, DEFAULT_DATABASE},
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        OnceLock, PoisonError, RwLock,
+    },
+    time::Duration,
+};
+
+pub mod joinbase;
+pub mod pgwire;
+pub mod timescale;
+
+/// the outcome of one query execution
+pub enum QueryOutcome {
+    /// the number of returned rows
+    Ok(usize),
+    Error(String),
+    Timeout,
+}
+
+/// A connection to a target for running queries,
+/// which is created and used in one bench thread.
+pub trait TargetConnection {
+    /// Executes the query and returns its outcome and the end-to-end time.
+    fn execute_query(&self, sql: &str) -> (QueryOutcome, Duration);
+}
+
+/// The connection and ingest settings, from which targets are created.
+/// Every target picks the settings it needs.
+#[derive(Debug, Clone)]
+pub struct TargetConfig {
+    /// JoinBase server url part for MQTT endpoint
+    pub ib_srv_part_mqtt: String,
+    /// JoinBase server url part for pg wire protocol endpoint
+    pub ib_srv_part_pg: String,
+    /// Postgresql server url part for any timescale/postgresql wire protocol endpoints
+    pub pg_srv_part: String,
+    /// the database connected to via the url parts
+    pub database: String,
+    /// the number of workers for batch ingest
+    pub num_workers: i32,
+    /// the number of rows in one message for message based ingest
+    pub num_rows_in_batch: i32,
+    /// the SQL returning the bytes on disk of the dataset, which overrides the built-in one of the target
+    pub size_sql: Option<String>,
+    /// the connection profile the target is resolved by, which overrides the url parts
+    pub profile: Option<ConnectionProfile>,
+}
+
+impl TargetConfig {
+    /// the connection of the target: the resolved profile, otherwise the url part
+    pub fn connection(
+        &self,
+        target: &str,
+        srv_part: &str,
+    ) -> Result<ConnectionProfile, OidbsError> {
+        match &self.profile {
+            Some(p) => Ok(p.clone()),
+            None => ConnectionProfile::from_srv_part(target, srv_part, &self.database),
+        }
+    }
+}
+
+impl Default for TargetConfig {
+    fn default() -> Self {
+        Self {
+            ib_srv_part_mqtt: String::from("abc:abc@127.0.0.1:1883"),
+            ib_srv_part_pg: String::from("abc:abc@127.0.0.1:5433"),
+            pg_srv_part: String::from("postgres:postgres@127.0.0.1:5432"),
+            database: DEFAULT_DATABASE.into(),
+            num_workers: 1,
+            num_rows_in_batch: 1,
+            size_sql: None,
+            profile: None,
+        }
+    }
+}
+
+pub trait Target: Send + Sync {
+    /// the name of the target, which is used in CLI and as the file name in model directories
+    fn name(&self) -> &'static str;
+
+    /// Connects for running queries. With `query_timeout`, every query is aborted after the timeout.
+    fn connect(
+        &self,
+        query_timeout: Option<Duration>,
+    ) -> Result<Box<dyn TargetConnection>, OidbsError>;
+
+    /// Creates the schemas of the model, dropping the existed ones.
+    fn setup_schema(&self, model: &Model) -> Result<(), OidbsError>;
+
+    /// Imports the dataset of the model in `data_dir` as fast as possible.
+    fn bulk_ingest(&self, model: &Model, data_dir: &str) -> Result<(), OidbsError>;
+
+    /// Ingests the dataset of the model in `data_dir` at a fixed total rate(rows per second)
+    /// until `stop` is set, the ingested rows are counted into `ingested`.
+    fn stream_ingest(
+        &self,
+        _model: &Model,
+        _data_dir: &str,
+        _rate: u64,
+        _stop: &AtomicBool,
+        _ingested: &AtomicU64,
+    ) -> Result<(), OidbsError> {
+        Err(OidbsError::Unsupported(format!(
+            "stream ingest for target {}",
+            self.name()
+        )))
+    }
+
+    /// the SQL dialect of the target, to which the neutral queries are lowered
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    /// the version string reported by the server, `None` if it can not be fetched
+    fn fetch_server_version(&self) -> Option<String>;
+
+    /// the name of the table of the model, qualified by its database if needed
+    fn qualified_table(&self, model: &Model) -> Result<String, OidbsError> {
+        Err(OidbsError::Unsupported(format!(
+            "table of model {} for target {}",
+            model.name,
+            self.name()
+        )))
+    }
+
+    /// Runs the query and returns all rows as text, NULLs are `None`.
+    fn fetch_rows(&self, _sql: &str) -> Result<Vec<Vec<Option<String>>>, OidbsError> {
+        Err(OidbsError::Unsupported(format!(
+            "fetching rows for target {}",
+            self.name()
+        )))
+    }
+
+    /// the number of rows in the table of the model
+    fn count_rows(&self, model: &Model) -> Result<u64, OidbsError> {
+        let sql = format!("select count(*) from {}", self.qualified_table(model)?);
+        let rows = self.fetch_rows(&sql)?;
+        rows.first()
+            .and_then(|r| r.first().cloned().flatten())
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| OidbsError::QueryFailed(format!("no count returned by: {}", sql)))
+    }
+
+    /// the bytes on disk of the dataset of the model, e.g. by `hypertable_size` for TimescaleDB.
+    /// `None` if the target can not tell.
+    fn storage_size(&self, _model: &Model) -> Result<Option<u64>, OidbsError> {
+        Ok(None)
+    }
+
+    /// Drops the tables of the model.
+    fn teardown(&self, model: &Model) -> Result<(), OidbsError>;
+}
+
+impl std::fmt::Debug for dyn Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Fills in the `{database}` and `{table}` placeholders of the configured size SQL.
+pub(crate) fn render_size_sql(sql: &str, info: &TargetInfo) -> String {
+    sql.replace("{database}", &info.database)
+        .replace("{table}", &info.table)
+}
+
+/// creates a target from the config
+pub type TargetFactory = fn(&TargetConfig) -> Result<Box<dyn Target>, OidbsError>;
+
+fn registry() -> &'static RwLock<Vec<(&'static str, TargetFactory)>> {
+    static REGISTRY: OnceLock<RwLock<Vec<(&'static str, TargetFactory)>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        RwLock::new(vec![
+            ("joinbase", joinbase::JoinBase::create as TargetFactory),
+            ("timescale", timescale::TimeScale::create as TargetFactory),
+        ])
+    })
+}
+
+/// Registers a target under `name`, which replaces the existed one with the same name.
+pub fn register(name: &'static str, factory: TargetFactory) {
+    let mut r = registry().write().unwrap_or_else(PoisonError::into_inner);
+    r.retain(|(n, _)| *n != name);
+    r.push((name, factory));
+}
+
+/// names of all registered targets
+pub fn names() -> Vec<&'static str> {
+    registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(n, _)| *n)
+        .collect()
+}
+
+/// Creates the target registered under `name`.
+pub fn create(name: &str, config: &TargetConfig) -> Result<Box<dyn Target>, OidbsError> {
+    let factory = registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, f)| *f);
+    match factory {
+        Some(f) => f(config),
+        None => Err(OidbsError::InvalidArgs(format!(
+            "target {}, options included of {}",
+            name,
+            names().join(", ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create, names, register, Target, TargetConfig, TargetConnection};
+    use crate::{error::OidbsError, model::Model};
+    use std::time::Duration;
+
+    struct Dummy;
+
+    impl Target for Dummy {
+        fn name(&self) -> &'static str {
+            "dummy"
+        }
+        fn connect(&self, _: Option<Duration>) -> Result<Box<dyn TargetConnection>, OidbsError> {
+            Err(OidbsError::Generic("dummy"))
+        }
+        fn setup_schema(&self, _: &Model) -> Result<(), OidbsError> {
+            Ok(())
+        }
+        fn bulk_ingest(&self, _: &Model, _: &str) -> Result<(), OidbsError> {
+            Ok(())
+        }
+        fn fetch_server_version(&self) -> Option<String> {
+            Some("dummy 1.0".into())
+        }
+        fn teardown(&self, _: &Model) -> Result<(), OidbsError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let config = TargetConfig::default();
+        assert_eq!(create("joinbase", &config).unwrap().name(), "joinbase");
+        assert!(create("dummy", &config).is_err());
+        register("dummy", |_| Ok(Box::new(Dummy)));
+        assert!(names().contains(&"dummy"));
+        let t = create("dummy", &config).unwrap();
+        assert_eq!(t.fetch_server_version().as_deref(), Some("dummy 1.0"));
+    }
+}
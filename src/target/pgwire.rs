@@ -0,0 +1,213 @@
+//! Helpers for the targets speaking the postgresql wire protocol, via libpq.
+
+use super::{QueryOutcome, TargetConnection};
+use crate::error::OidbsError;
+use libpq::Status::*;
+use log::*;
+use std::{
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn check(result: &libpq::Result) -> Result<(), OidbsError> {
+    match result.status() {
+        BadResponse | FatalError | NonFatalError => Err(OidbsError::QueryFailed(
+            result.error_message()?.unwrap_or_default(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Runs the statements, which may be many ones separated by `;`, in one simple query.
+pub fn exec_batch(uri: &str, sql: &str) -> Result<(), OidbsError> {
+    debug!("to run query: {}", sql);
+    let conn = libpq::Connection::new(uri)?;
+    check(&conn.exec(sql))
+}
+
+/// Runs the query and returns the first column of the first row.
+pub fn query_scalar(uri: &str, sql: &str) -> Result<Option<String>, OidbsError> {
+    let conn = libpq::Connection::new(uri)?;
+    let result = conn.exec(sql);
+    check(&result)?;
+    Ok(result
+        .value(0, 0)
+        .map(|v| String::from_utf8_lossy(v).into_owned()))
+}
+
+/// Runs the query and returns all rows, NULLs are `None`.
+pub fn query_rows(uri: &str, sql: &str) -> Result<Vec<Vec<Option<String>>>, OidbsError> {
+    let conn = libpq::Connection::new(uri)?;
+    let result = conn.exec(sql);
+    check(&result)?;
+    Ok((0..result.ntuples())
+        .map(|r| {
+            (0..result.nfields())
+                .map(|c| result.value(r, c).map(|v| String::from_utf8_lossy(v).into_owned()))
+                .collect()
+        })
+        .collect())
+}
+
+/// Runs the query returning a size in bytes, `None` for a NULL.
+pub fn query_size(uri: &str, sql: &str) -> Result<Option<u64>, OidbsError> {
+    match query_scalar(uri, sql)? {
+        // `numeric` results may have a fractional part
+        Some(v) => match v.trim().parse::<f64>() {
+            Ok(n) if n >= 0.0 => Ok(Some(n as u64)),
+            _ => Err(OidbsError::QueryFailed(format!("not a size: {}", v))),
+        },
+        None => Ok(None),
+    }
+}
+
+pub fn fetch_server_version(uri: &str) -> Option<String> {
+    query_scalar(uri, "select version()").ok().flatten()
+}
+
+/// A guard thread which cancels the running query of a connection via libpq's cancel API,
+/// once the armed deadline is passed.
+struct Watchdog {
+    state: Arc<(Mutex<GuardState>, Condvar)>,
+}
+
+#[derive(Default)]
+struct GuardState {
+    deadline: Option<Instant>,
+    /// whether the query of the current deadline has been canceled
+    fired: bool,
+    closed: bool,
+}
+
+struct CancelHandle(libpq::connection::Cancel);
+
+// SAFETY: libpq documents `PQcancel` as safe to be invoked from a thread other than
+// the one manipulating the connection, which is the only use of the wrapped handle.
+unsafe impl Send for CancelHandle {}
+
+impl Watchdog {
+    fn new(conn: &libpq::Connection) -> Self {
+        let state = Arc::new((Mutex::new(GuardState::default()), Condvar::new()));
+        let cancel = CancelHandle(conn.cancel());
+        let in_guard = state.clone();
+        // the guard thread exits once the watchdog is dropped with the connection
+        thread::spawn(move || {
+            let cancel = cancel;
+            let (lock, cvar) = &*in_guard;
+            let mut st = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            while !st.closed {
+                st = match st.deadline {
+                    None => cvar.wait(st).unwrap_or_else(PoisonError::into_inner),
+                    Some(d) if Instant::now() < d => {
+                        let left = d.saturating_duration_since(Instant::now());
+                        cvar.wait_timeout(st, left)
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .0
+                    }
+                    Some(_) => {
+                        // the lock is held until the server has taken the cancel request,
+                        // so a disarmed query is never canceled
+                        st.deadline = None;
+                        st.fired = true;
+                        if let Err(e) = cancel.0.request() {
+                            warn!("fail to cancel the timed out query: {:?}", e);
+                        }
+                        st
+                    }
+                };
+            }
+        });
+        Self { state }
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut GuardState) -> T) -> T {
+        let (lock, cvar) = &*self.state;
+        let rt = f(&mut lock.lock().unwrap_or_else(PoisonError::into_inner));
+        cvar.notify_one();
+        rt
+    }
+
+    fn arm(&self, timeout: Duration) {
+        self.update(|st| {
+            st.fired = false;
+            st.deadline = Some(Instant::now() + timeout);
+        });
+    }
+
+    /// returns true if the query has been canceled, no cancel request is sent after returning
+    fn disarm(&self) -> bool {
+        self.update(|st| {
+            st.deadline = None;
+            st.fired
+        })
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.update(|st| st.closed = true);
+    }
+}
+
+/// A pg wire protocol connection, which enforces the query timeout and classifies the outcomes of queries.
+pub struct PgWireConnection {
+    conn: libpq::Connection,
+    timeout: Option<Duration>,
+    watchdog: Option<Watchdog>,
+}
+
+impl PgWireConnection {
+    /// Connects to the `uri`. If `statement_timeout` is true, the server side `statement_timeout`
+    /// is used for the query timeout, otherwise the queries are canceled by the client.
+    pub fn connect(
+        uri: &str,
+        timeout: Option<Duration>,
+        statement_timeout: bool,
+    ) -> Result<Self, OidbsError> {
+        let conn = libpq::Connection::new(uri)?;
+        let mut watchdog = None;
+        if let Some(timeout) = timeout {
+            if statement_timeout {
+                let sql = format!("SET statement_timeout = {}", timeout.as_millis());
+                check(&conn.exec(&sql))?;
+            } else {
+                watchdog = Some(Watchdog::new(&conn));
+            }
+        }
+        Ok(Self {
+            conn,
+            timeout,
+            watchdog,
+        })
+    }
+}
+
+impl TargetConnection for PgWireConnection {
+    fn execute_query(&self, sql: &str) -> (QueryOutcome, Duration) {
+        if let (Some(wd), Some(timeout)) = (&self.watchdog, self.timeout) {
+            wd.arm(timeout);
+        }
+        let ts = Instant::now();
+        let result = self.conn.exec(sql);
+        let time = ts.elapsed();
+        let canceled = self.watchdog.as_ref().is_some_and(|wd| wd.disarm());
+        let outcome = match result.status() {
+            BadResponse | FatalError | NonFatalError => {
+                if canceled || self.timeout.is_some_and(|t| time >= t) {
+                    QueryOutcome::Timeout
+                } else {
+                    QueryOutcome::Error(
+                        result
+                            .error_message()
+                            .ok()
+                            .flatten()
+                            .unwrap_or_default(),
+                    )
+                }
+            }
+            _ => QueryOutcome::Ok(result.ntuples()),
+        };
+        (outcome, time)
+    }
+}
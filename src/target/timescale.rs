@@ -0,0 +1,159 @@
+//! TimescaleDB, which ingests via `timescaledb-parallel-copy` and is queried via the pg wire protocol.
+
+use super::{pgwire, Target, TargetConfig, TargetConnection};
+use crate::{
+    error::OidbsError,
+    manifest,
+    model::{Model, TargetInfo},
+    profile::ConnectionProfile,
+};
+use log::*;
+use std::{
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+const PG_PORT: u16 = 5432;
+
+pub struct TimeScale {
+    pg: ConnectionProfile,
+    /// the connection string of `pg`, which contains the password
+    conninfo: String,
+    num_workers: i32,
+    size_sql: Option<String>,
+}
+
+impl TimeScale {
+    pub fn create(config: &TargetConfig) -> Result<Box<dyn Target>, OidbsError> {
+        let pg = config.connection("timescale", &config.pg_srv_part)?;
+        Ok(Box::new(TimeScale {
+            conninfo: pg.conninfo(PG_PORT, true)?,
+            pg,
+            num_workers: config.num_workers,
+            size_sql: config.size_sql.clone(),
+        }))
+    }
+
+    /// The table of the timescale schema, or the one of JoinBase for the models without it.
+    /// The unqualified table is in the database of the connection.
+    fn table_info(&self, model: &Model) -> Result<TargetInfo, OidbsError> {
+        let mut info = ["timescale", "joinbase"]
+            .iter()
+            .filter_map(|t| model.target_infos.get(*t))
+            .find(|i| !i.table.is_empty())
+            .cloned()
+            .ok_or_else(|| OidbsError::UnimplementedModel(model.name.clone()))?;
+        if info.database.is_empty() {
+            info.database = self.pg.database.clone();
+        }
+        Ok(info)
+    }
+}
+
+impl Target for TimeScale {
+    fn name(&self) -> &'static str {
+        "timescale"
+    }
+
+    fn connect(
+        &self,
+        query_timeout: Option<Duration>,
+    ) -> Result<Box<dyn TargetConnection>, OidbsError> {
+        let conn = pgwire::PgWireConnection::connect(&self.conninfo, query_timeout, true)?;
+        Ok(Box::new(conn))
+    }
+
+    fn setup_schema(&self, model: &Model) -> Result<(), OidbsError> {
+        println!("[setup_schemas]to connect to timescale server: {}", self.pg);
+        match model.target_infos.get(self.name()) {
+            Some(v) => pgwire::exec_batch(&self.conninfo, &v.schema),
+            None => {
+                debug!("no {} schema found for target: timescale", model.name);
+                Ok(())
+            }
+        }
+    }
+
+    /// Uses the official `timescaledb-parallel-copy` tool, which should be in the system path,
+    /// because the common postgresql way to import a relative big dataset is very slow.
+    fn bulk_ingest(&self, model: &Model, data_dir: &str) -> Result<(), OidbsError> {
+        let model_dir = data_dir.to_string() + "/" + &model.name;
+        debug!("model_dir: {}", model_dir);
+        let schema = self.table_info(model)?;
+        // the password is passed via `PGPASSWORD`, so it is neither logged nor shown in `ps`
+        let con_str = self.pg.conninfo(PG_PORT, false)?;
+        let password = self.pg.password()?;
+        // the columns of the data files, which are in the layout of the JoinBase table
+        let columns = model
+            .target_infos
+            .get("joinbase")
+            .map_or_else(|| schema.column_names(), |i| i.column_names())
+            .join(",");
+        debug!("con_str: {}", con_str);
+        for file_path in manifest::data_files(&model_dir)? {
+            println!("-> to import: {:?}", file_path);
+
+            //timescaledb-parallel-copy --db-name nyc_data --table rides --file ./nyc_data_rides.csv --workers 4 --reporting-period 10s
+            let status = Command::new("timescaledb-parallel-copy")
+                .arg("--connection")
+                .arg(&con_str)
+                .arg("--db-name")
+                .arg(&schema.database)
+                .arg("--table")
+                .arg(&schema.table)
+                .arg("--columns")
+                .arg(&columns)
+                .arg("--file")
+                .arg(&file_path)
+                .arg("--workers")
+                .arg(self.num_workers.to_string())
+                .arg("--reporting-period")
+                .arg("10s")
+                .envs(password.iter().map(|p| ("PGPASSWORD", p)))
+                .stdout(Stdio::inherit())
+                .stderr(Stdio::inherit())
+                .status()?;
+            if !status.success() {
+                return Err(OidbsError::Generic("timescaledb-parallel-copy failed"));
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch_server_version(&self) -> Option<String> {
+        pgwire::fetch_server_version(&self.conninfo)
+    }
+
+    fn qualified_table(&self, model: &Model) -> Result<String, OidbsError> {
+        Ok(self.table_info(model)?.table.clone())
+    }
+
+    fn fetch_rows(&self, sql: &str) -> Result<Vec<Vec<Option<String>>>, OidbsError> {
+        pgwire::query_rows(&self.conninfo, sql)
+    }
+
+    /// The size of a hypertable includes all its chunks and indexes,
+    /// `pg_total_relation_size` is used for a plain table.
+    fn storage_size(&self, model: &Model) -> Result<Option<u64>, OidbsError> {
+        let info = self.table_info(model)?;
+        let uri = &self.conninfo;
+        if let Some(sql) = &self.size_sql {
+            return pgwire::query_size(uri, &super::render_size_sql(sql, &info));
+        }
+        match pgwire::query_size(uri, &format!("select hypertable_size('{}')", info.table)) {
+            Ok(Some(size)) => Ok(Some(size)),
+            _ => pgwire::query_size(
+                uri,
+                &format!("select pg_total_relation_size('{}')", info.table),
+            ),
+        }
+    }
+
+    fn teardown(&self, model: &Model) -> Result<(), OidbsError> {
+        let schema = self.table_info(model)?;
+        pgwire::exec_batch(
+            &self.conninfo,
+            &format!("drop table if exists {}", schema.table),
+        )
+    }
+}
@@ -0,0 +1,252 @@
+//! Query templates with randomized arguments.
+//!
+//! A query may contain placeholders, which are filled in per execution,
+//! so repeated runs do not hit the same data and the result caches of engines:
+//!
+//! * `{{rand_time_range:1h}}`, a neutral `TIME_RANGE` predicate selecting a random range of
+//!   the given length on the time column of the model, `{{rand_time_range:1h:col}}` for another column.
+//!   Lengths are in `s`, `m`, `h` or `d`.
+//! * `{{rand_station_id}}`, a random station id of the model.
+//! * `{{rand_int:1..200}}`, a random integer in the half-open range, or `1..=200` for the closed one.
+//!
+//! The rendered query is in the neutral form, which is lowered to target dialects by [`Dialect`].
+//!
+//! [`Dialect`]: crate::bench::dialect::Dialect
+
+use crate::{error::OidbsError, manifest::DatasetManifest, model::Model};
+use chrono::{Duration, NaiveDateTime};
+use rand::{rngs::SmallRng, Rng};
+
+/// the bounds of the data of a model, within which the random arguments are drawn
+#[derive(Debug, Clone)]
+pub struct DataBounds {
+    pub time_column: String,
+    pub time_start: NaiveDateTime,
+    /// exclusive
+    pub time_end: NaiveDateTime,
+    pub num_stations: u32,
+}
+
+fn parse_ts(s: &str) -> Result<NaiveDateTime, OidbsError> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .map_err(|_| OidbsError::InvalidArgs(format!("timestamp '{}'", s)))
+}
+
+impl DataBounds {
+    /// the bounds of the reference datasets of the models, which do not match the ones generated with other
+    /// settings, so the bounds of a manifest are preferred
+    pub fn of_model(model: &Model) -> Result<Self, OidbsError> {
+        Ok(if model.name.starts_with("nyct") {
+            DataBounds {
+                time_column: "pickup_datetime".into(),
+                time_start: parse_ts("2016-01-01 00:00:00")?,
+                time_end: parse_ts("2016-02-01 00:00:00")?,
+                num_stations: 1,
+            }
+        } else {
+            DataBounds {
+                time_column: "ts".into(),
+                time_start: parse_ts("2021-01-01 00:00:01")?,
+                time_end: parse_ts("2021-01-02 00:00:01")?,
+                num_stations: 5_000,
+            }
+        })
+    }
+
+    /// the bounds of the generated dataset described by its manifest
+    pub fn of_manifest(model: &Model, manifest: &DatasetManifest) -> Result<Self, OidbsError> {
+        let ts = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+                .map_err(|_| OidbsError::InvalidArgs(format!("timestamp '{}' of the manifest", s)))
+        };
+        let defaults = Self::of_model(model)?;
+        Ok(DataBounds {
+            time_start: ts(&manifest.time_start)?,
+            time_end: ts(&manifest.time_end)?,
+            num_stations: manifest
+                .model_parameters
+                .get("num_stations")
+                .and_then(|v| v.as_u64())
+                .map_or(defaults.num_stations, |n| n as u32),
+            time_column: defaults.time_column,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    TimeRange { len: Duration, column: Option<String> },
+    StationId,
+    Int { lo: i64, hi: i64 },
+}
+
+/// A parsed query template, which is rendered into a concrete query for every execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTemplate {
+    parts: Vec<Part>,
+}
+
+fn invalid(placeholder: &str) -> OidbsError {
+    OidbsError::InvalidArgs(format!("query placeholder {{{{{}}}}}", placeholder))
+}
+
+fn parse_len(s: &str) -> Option<Duration> {
+    let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit())?);
+    let n: i64 = n.parse().ok()?;
+    match unit {
+        "s" => Duration::try_seconds(n),
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        _ => None,
+    }
+    .filter(|d| *d > Duration::zero())
+}
+
+fn parse_placeholder(p: &str) -> Result<Part, OidbsError> {
+    let mut args = p.trim().split(':');
+    let part = match (args.next(), args.next(), args.next()) {
+        (Some("rand_time_range"), Some(len), column) => Part::TimeRange {
+            len: parse_len(len).ok_or_else(|| invalid(p))?,
+            column: column.map(|c| c.trim().to_string()),
+        },
+        (Some("rand_station_id"), None, None) => Part::StationId,
+        (Some("rand_int"), Some(range), None) => {
+            let (lo, hi, inclusive) = match range.split_once("..=") {
+                Some((lo, hi)) => (lo, hi, true),
+                None => {
+                    let (lo, hi) = range.split_once("..").ok_or_else(|| invalid(p))?;
+                    (lo, hi, false)
+                }
+            };
+            let lo: i64 = lo.trim().parse().map_err(|_| invalid(p))?;
+            let hi: i64 = hi.trim().parse().map_err(|_| invalid(p))?;
+            let hi = match inclusive {
+                true => hi.checked_add(1).ok_or_else(|| invalid(p))?,
+                false => hi,
+            };
+            if lo >= hi {
+                return Err(invalid(p));
+            }
+            Part::Int { lo, hi }
+        }
+        _ => return Err(invalid(p)),
+    };
+    if args.next().is_some() {
+        return Err(invalid(p));
+    }
+    Ok(part)
+}
+
+impl QueryTemplate {
+    pub fn parse(sql: &str) -> Result<Self, OidbsError> {
+        let mut parts = vec![];
+        let mut rest = sql;
+        while let Some(st) = rest.find("{{") {
+            let ed = rest[st..]
+                .find("}}")
+                .ok_or_else(|| invalid(&rest[st + 2..]))?;
+            if st > 0 {
+                parts.push(Part::Literal(rest[..st].to_string()));
+            }
+            parts.push(parse_placeholder(&rest[st + 2..st + ed])?);
+            rest = &rest[st + ed + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// whether the template has no placeholder, then every rendering is the same
+    pub fn is_fixed(&self) -> bool {
+        self.parts.iter().all(|p| matches!(p, Part::Literal(_)))
+    }
+
+    /// Renders a concrete query in the neutral form, drawing arguments from `rng`.
+    pub fn render(&self, bounds: &DataBounds, rng: &mut SmallRng) -> String {
+        let mut sql = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => sql.push_str(s),
+                Part::TimeRange { len, column } => {
+                    // a range longer than the data selects all of it
+                    let len = &(*len).min(bounds.time_end - bounds.time_start);
+                    let span = (bounds.time_end - bounds.time_start - *len).num_seconds();
+                    let start =
+                        bounds.time_start + Duration::seconds(rng.gen_range(0..=span.max(0)));
+                    let column = column.as_deref().unwrap_or(&bounds.time_column);
+                    let fmt = "%Y-%m-%d %H:%M:%S";
+                    sql.push_str(&format!(
+                        "TIME_RANGE({}, '{}', '{}')",
+                        column,
+                        start.format(fmt),
+                        (start + *len).format(fmt)
+                    ));
+                }
+                Part::StationId => {
+                    sql.push_str(&rng.gen_range(0..bounds.num_stations.max(1)).to_string())
+                }
+                Part::Int { lo, hi } => sql.push_str(&rng.gen_range(*lo..*hi).to_string()),
+            }
+        }
+        sql
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ts, DataBounds, QueryTemplate};
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    #[test]
+    fn test_render_template() {
+        let bounds = DataBounds {
+            time_column: "ts".into(),
+            time_start: parse_ts("2021-01-01 00:00:00").unwrap(),
+            time_end: parse_ts("2021-01-01 02:00:00").unwrap(),
+            num_stations: 10,
+        };
+        let t = QueryTemplate::parse(
+            "select count(*) from pstations where {{rand_time_range:1h}} and station_id={{rand_station_id}} and sensor_id<{{rand_int:1..=200}}",
+        )
+        .unwrap();
+        assert!(!t.is_fixed());
+        // the same seed draws the same values
+        let a = t.render(&bounds, &mut SmallRng::seed_from_u64(1));
+        let b = t.render(&bounds, &mut SmallRng::seed_from_u64(1));
+        assert!(a.contains("where TIME_RANGE(ts, '2021-01-01 0"), "{}", a);
+        assert_eq!(a, b);
+
+        let mut rng = SmallRng::seed_from_u64(2);
+        for _ in 0..100 {
+            let sql = t.render(&bounds, &mut rng);
+            let start = &sql[sql.find("TIME_RANGE(ts, '").unwrap() + 16..][..19];
+            assert!(parse_ts(start).unwrap() <= parse_ts("2021-01-01 01:00:00").unwrap());
+            let station: u32 = sql[sql.find("station_id=").unwrap() + 11..]
+                .split(' ')
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(station < 10);
+        }
+
+        let fixed = QueryTemplate::parse("select 1").unwrap();
+        assert!(fixed.is_fixed());
+        assert_eq!(fixed.render(&bounds, &mut rng), "select 1");
+        assert!(QueryTemplate::parse("select {{rand_int:5..5}}").is_err());
+        assert!(QueryTemplate::parse("select {{rand_time_range:1y}}").is_err());
+        assert!(QueryTemplate::parse("select {{rand_station_id").is_err());
+        assert!(parse_ts("2021-01-01").is_err());
+        // out of the ranges of durations and integers
+        assert!(QueryTemplate::parse("select {{rand_time_range:999999999999999d}}").is_err());
+        assert!(QueryTemplate::parse("select {{rand_int:0..=9223372036854775807}}").is_err());
+        let all = QueryTemplate::parse("select {{rand_time_range:30d}}").unwrap();
+        assert_eq!(
+            all.render(&bounds, &mut rng),
+            "select TIME_RANGE(ts, '2021-01-01 00:00:00', '2021-01-01 02:00:00')"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Verification of an imported dataset against its input files.
+//!
+//! The rows on the target are counted and compared with the lines of the input files,
+//! so lost or duplicated rows are reported explicitly. Optionally, aggregates of numeric
+//! columns(count, sum, min and max) are compared per day partition of the time column.
+
+use crate::{
+    error::OidbsError, manifest, model::Model, target::Target, template::DataBounds,
+};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// the relative tolerance of comparing floating sums, for the precision of `Float32` columns
+const TOLERANCE: f64 = 1e-4;
+
+/// the aggregates of one column in one partition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnAgg {
+    /// the number of non-null values
+    pub n: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for ColumnAgg {
+    fn default() -> Self {
+        ColumnAgg {
+            n: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl ColumnAgg {
+    fn add(&mut self, v: f64) {
+        self.n += 1;
+        self.sum += v;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+    }
+
+    fn matches(&self, other: &ColumnAgg) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0);
+        self.n == other.n
+            && (self.n == 0
+                || close(self.sum, other.sum)
+                    && close(self.min, other.min)
+                    && close(self.max, other.max))
+    }
+}
+
+/// the number of rows and the aggregates of the checked columns in one partition
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PartitionStats {
+    pub num_rows: u64,
+    pub columns: Vec<ColumnAgg>,
+}
+
+/// the partitions by day, `YYYY-MM-DD`
+pub type Checksums = BTreeMap<String, PartitionStats>;
+
+/// the outcome of the verification
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub expected_rows: u64,
+    pub actual_rows: u64,
+    /// the partitions whose row counts or aggregates differ, with the descriptions
+    pub mismatches: Vec<String>,
+}
+
+impl Verification {
+    pub fn lost_rows(&self) -> u64 {
+        self.expected_rows.saturating_sub(self.actual_rows)
+    }
+
+    pub fn duplicated_rows(&self) -> u64 {
+        self.actual_rows.saturating_sub(self.expected_rows)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.expected_rows == self.actual_rows && self.mismatches.is_empty()
+    }
+}
+
+/// Counts the rows of the target until it reaches `expected`, or stops changing,
+/// because the rows published via MQTT are ingested asynchronously.
+fn wait_for_count(
+    target: &dyn Target,
+    model: &Model,
+    expected: u64,
+    wait: Duration,
+) -> Result<u64, OidbsError> {
+    let ts = Instant::now();
+    let mut last = None;
+    let mut unchanged = 0;
+    loop {
+        let count = target.count_rows(model)?;
+        if count == expected || ts.elapsed() >= wait {
+            return Ok(count);
+        }
+        if last == Some(count) {
+            unchanged += 1;
+            // the duplicated rows never go away
+            if unchanged >= 5 && count > expected {
+                return Ok(count);
+            }
+        } else {
+            unchanged = 0;
+        }
+        last = Some(count);
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Accumulates one input row into the checksums, in which `time_idx` is the index of the time
+/// column and `column_idxs` are the indexes of the checked columns.
+pub fn accumulate(
+    checksums: &mut Checksums,
+    line: &str,
+    time_idx: usize,
+    column_idxs: &[usize],
+) -> Result<(), OidbsError> {
+    let fields: Vec<&str> = line.split(',').collect();
+    let day = fields
+        .get(time_idx)
+        .and_then(|t| t.trim().trim_matches('"').get(..10))
+        .ok_or_else(|| OidbsError::InvalidArgs(format!("input row '{}'", line)))?;
+    let p = checksums
+        .entry(day.to_string())
+        .or_insert_with(|| PartitionStats {
+            num_rows: 0,
+            columns: vec![ColumnAgg::default(); column_idxs.len()],
+        });
+    p.num_rows += 1;
+    for (agg, &i) in p.columns.iter_mut().zip(column_idxs) {
+        match fields.get(i).map(|v| v.trim()) {
+            None | Some("") => {}
+            Some(v) => agg.add(v.parse().map_err(|_| {
+                OidbsError::InvalidArgs(format!("non-numeric value '{}' of a checked column", v))
+            })?),
+        }
+    }
+    Ok(())
+}
+
+/// Computes the checksums on the target with a neutral query lowered to its dialect.
+fn target_checksums(
+    target: &dyn Target,
+    model: &Model,
+    time_column: &str,
+    columns: &[String],
+) -> Result<Checksums, OidbsError> {
+    let aggs: String = columns
+        .iter()
+        .map(|c| format!(", count({c}), sum({c}), min({c}), max({c})", c = c))
+        .collect();
+    let sql = format!(
+        "select BUCKET_DAY({}) as day, count(*){} from {} group by day order by day",
+        time_column,
+        aggs,
+        target.qualified_table(model)?
+    );
+    let sql = target.dialect().translate(&sql)?;
+    let num = |v: &Option<String>| -> Result<f64, OidbsError> {
+        match v {
+            Some(v) => v
+                .trim()
+                .parse()
+                .map_err(|_| OidbsError::QueryFailed(format!("not a number: {}", v))),
+            None => Ok(0.0),
+        }
+    };
+    let mut rt = Checksums::new();
+    for row in target.fetch_rows(&sql)? {
+        if row.len() != 2 + 4 * columns.len() {
+            return Err(OidbsError::QueryFailed(format!(
+                "unexpected number of columns of checksums: {}",
+                row.len()
+            )));
+        }
+        let day = row[0].as_deref().unwrap_or_default();
+        let day = day.get(..10).unwrap_or(day).to_string();
+        let columns = row[2..]
+            .chunks(4)
+            .map(|c| {
+                Ok(ColumnAgg {
+                    n: num(&c[0])? as u64,
+                    sum: num(&c[1])?,
+                    min: num(&c[2])?,
+                    max: num(&c[3])?,
+                })
+            })
+            .collect::<Result<Vec<_>, OidbsError>>()?;
+        rt.insert(
+            day,
+            PartitionStats {
+                num_rows: num(&row[1])? as u64,
+                columns,
+            },
+        );
+    }
+    Ok(rt)
+}
+
+/// Describes the partitions which differ.
+pub fn compare_checksums(
+    expected: &Checksums,
+    actual: &Checksums,
+    columns: &[String],
+) -> Vec<String> {
+    let mut rt = vec![];
+    let empty = PartitionStats::default();
+    let days: std::collections::BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+    for day in days {
+        let e = expected.get(day).unwrap_or(&empty);
+        let a = actual.get(day).unwrap_or(&empty);
+        if e.num_rows != a.num_rows {
+            rt.push(format!(
+                "{}: {} rows expected, {} rows found",
+                day, e.num_rows, a.num_rows
+            ));
+            continue;
+        }
+        for (i, c) in columns.iter().enumerate() {
+            match (e.columns.get(i), a.columns.get(i)) {
+                (Some(e), Some(a)) if e.matches(a) => {}
+                (e, a) => rt.push(format!(
+                    "{}: checksums of column {} differ, expected {:?}, found {:?}",
+                    day, c, e, a
+                )),
+            }
+        }
+    }
+    rt
+}
+
+/// Verifies the imported dataset of the model against the input files in `data_dir`.
+pub fn verify(
+    target: &dyn Target,
+    model: &Model,
+    data_dir: &str,
+    expected_rows: u64,
+    columns: &[String],
+    wait: Duration,
+) -> Result<Verification, OidbsError> {
+    let actual_rows = wait_for_count(target, model, expected_rows, wait)?;
+    let mut mismatches = vec![];
+    if !columns.is_empty() {
+        let layout = model
+            .target_infos
+            .get("joinbase")
+            .ok_or_else(|| OidbsError::UnimplementedModel(model.name.clone()))?
+            .column_names();
+        let index_of = |c: &str| {
+            layout
+                .iter()
+                .position(|l| l == c)
+                .ok_or_else(|| OidbsError::InvalidArgs(format!("column {} to verify", c)))
+        };
+        let time_column = DataBounds::of_model(model)?.time_column;
+        let time_idx = index_of(&time_column)?;
+        let column_idxs = columns
+            .iter()
+            .map(|c| index_of(c))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut expected = Checksums::new();
+        for path in manifest::data_files(manifest::model_dir(data_dir, &model.name))? {
+            let file = File::open(&path).map_err(|e| OidbsError::file(&path, e))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| OidbsError::file(&path, e))?;
+                accumulate(&mut expected, &line, time_idx, &column_idxs)?;
+            }
+        }
+        let actual = target_checksums(target, model, &time_column, columns)?;
+        mismatches = compare_checksums(&expected, &actual, columns);
+    }
+    Ok(Verification {
+        expected_rows,
+        actual_rows,
+        mismatches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accumulate, compare_checksums, Checksums, Verification};
+
+    #[test]
+    fn test_checksums() {
+        let rows = [
+            "1,0,2021-01-01 00:00:01,1.5",
+            "2,0,2021-01-01 10:00:01,2.5",
+            "3,0,2021-01-02 00:00:01,",
+        ];
+        let mut expected = Checksums::new();
+        for r in rows {
+            accumulate(&mut expected, r, 2, &[0, 3]).unwrap();
+        }
+        assert_eq!(expected.len(), 2);
+        let d1 = &expected["2021-01-01"];
+        assert_eq!(d1.num_rows, 2);
+        assert_eq!(
+            (d1.columns[0].sum, d1.columns[0].min, d1.columns[0].max),
+            (3.0, 1.0, 2.0)
+        );
+        assert_eq!(d1.columns[1].sum, 4.0);
+        // nulls are not counted
+        assert_eq!(expected["2021-01-02"].columns[1].n, 0);
+
+        let columns = ["station_id".to_string(), "v".to_string()];
+        assert!(compare_checksums(&expected, &expected, &columns).is_empty());
+
+        let mut actual = expected.clone();
+        actual.get_mut("2021-01-01").unwrap().columns[1].sum = 4.00001;
+        assert!(compare_checksums(&expected, &actual, &columns).is_empty());
+        actual.get_mut("2021-01-01").unwrap().columns[1].max = 3.0;
+        assert_eq!(compare_checksums(&expected, &actual, &columns).len(), 1);
+        actual.remove("2021-01-02");
+        let m = compare_checksums(&expected, &actual, &columns);
+        assert_eq!(m.len(), 2);
+        assert!(m[1].contains("1 rows expected, 0 rows found"));
+
+        assert!(accumulate(&mut Checksums::new(), "1,x", 0, &[]).is_err());
+        assert!(accumulate(&mut Checksums::new(), "a,2021-01-01 00:00:00", 1, &[0]).is_err());
+
+        let v = Verification {
+            expected_rows: 10,
+            actual_rows: 8,
+            mismatches: vec![],
+        };
+        assert_eq!(
+            (v.lost_rows(), v.duplicated_rows(), v.is_ok()),
+            (2, 0, false)
+        );
+    }
+}
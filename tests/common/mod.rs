@@ -0,0 +1,163 @@
+//! In-process stand-ins of the servers, for running import and bench without any database.
+
+use std::{
+    io::{self, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// the reply of the pg wire stand-in to a simple query
+pub enum Reply {
+    /// the rows of text columns
+    Rows(Vec<&'static str>, Vec<Vec<Option<String>>>),
+    /// the tag of a command without rows, e.g. `CREATE TABLE`
+    Command(&'static str),
+    Error(String),
+}
+
+type Handler = dyn Fn(&str) -> Reply + Send + Sync;
+
+/// A minimal server of the postgresql wire protocol(v3), which trusts every client
+/// and answers simple queries by the handler. The queries are recorded in the order received.
+pub struct PgStandIn {
+    port: u16,
+    queries: Arc<Mutex<Vec<String>>>,
+}
+
+impl PgStandIn {
+    pub fn start(handler: impl Fn(&str) -> Reply + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let queries = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let recorded = queries.clone();
+        // the listener lives until the test process exits
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (handler, recorded) = (handler.clone(), recorded.clone());
+                thread::spawn(move || {
+                    let _ = serve(stream, &*handler, &recorded);
+                });
+            }
+        });
+        Self { port, queries }
+    }
+
+    /// the url part `user:password@host:port` of the stand-in
+    pub fn srv_part(&self) -> String {
+        format!("abc:abc@127.0.0.1:{}", self.port)
+    }
+
+    pub fn queries(&self) -> Vec<String> {
+        self.queries.lock().unwrap().clone()
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler, recorded: &Mutex<Vec<String>>) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    let mut r = BufReader::new(stream);
+    loop {
+        let body = read_body(&mut r, 4)?;
+        match u32::from_be_bytes(body[..4].try_into().unwrap()) {
+            // SSLRequest
+            80877103 => out.write_all(b"N")?,
+            // CancelRequest, the queries are answered at once, so nothing to cancel
+            80877102 => return Ok(()),
+            _ => break,
+        }
+    }
+    let mut buf = vec![];
+    message(&mut buf, b'R', &0u32.to_be_bytes());
+    for (k, v) in [
+        ("server_version", "16.0"),
+        ("server_encoding", "UTF8"),
+        ("client_encoding", "UTF8"),
+        ("DateStyle", "ISO, MDY"),
+        ("integer_datetimes", "on"),
+        ("standard_conforming_strings", "on"),
+    ] {
+        message(&mut buf, b'S', format!("{}\0{}\0", k, v).as_bytes());
+    }
+    message(&mut buf, b'K', &[0, 0, 0, 1, 0, 0, 0, 1]);
+    message(&mut buf, b'Z', b"I");
+    out.write_all(&buf)?;
+
+    loop {
+        let mut kind = [0u8];
+        if r.read(&mut kind)? == 0 {
+            return Ok(());
+        }
+        let body = read_body(&mut r, 0)?;
+        buf.clear();
+        match kind[0] {
+            b'Q' => {
+                let sql = String::from_utf8_lossy(&body[..body.len() - 1]).into_owned();
+                recorded.lock().unwrap().push(sql.clone());
+                match handler(&sql) {
+                    Reply::Rows(columns, rows) => {
+                        let mut desc = (columns.len() as u16).to_be_bytes().to_vec();
+                        for c in &columns {
+                            desc.extend(c.as_bytes());
+                            desc.push(0);
+                            // no table, text(oid 25) of variable size, in text format
+                            desc.extend([0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 0xff, 0xff]);
+                            desc.extend([0xff, 0xff, 0xff, 0xff, 0, 0]);
+                        }
+                        message(&mut buf, b'T', &desc);
+                        for row in &rows {
+                            let mut data = (row.len() as u16).to_be_bytes().to_vec();
+                            for v in row {
+                                match v {
+                                    Some(v) => {
+                                        data.extend((v.len() as u32).to_be_bytes());
+                                        data.extend(v.as_bytes());
+                                    }
+                                    None => data.extend((-1i32).to_be_bytes()),
+                                }
+                            }
+                            message(&mut buf, b'D', &data);
+                        }
+                        message(
+                            &mut buf,
+                            b'C',
+                            format!("SELECT {}\0", rows.len()).as_bytes(),
+                        );
+                    }
+                    Reply::Command(tag) => message(&mut buf, b'C', format!("{}\0", tag).as_bytes()),
+                    Reply::Error(msg) => message(
+                        &mut buf,
+                        b'E',
+                        format!("SERROR\0VERROR\0C42000\0M{}\0\0", msg).as_bytes(),
+                    ),
+                }
+                message(&mut buf, b'Z', b"I");
+            }
+            // Terminate
+            b'X' => return Ok(()),
+            _ => {
+                message(&mut buf, b'E', b"SERROR\0C0A000\0Monly simple queries\0\0");
+                message(&mut buf, b'Z', b"I");
+            }
+        }
+        out.write_all(&buf)?;
+    }
+}
+
+/// Reads the body of a message after its length, which has `min` bytes at least.
+fn read_body(r: &mut impl Read, min: usize) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    let len = (u32::from_be_bytes(len) as usize)
+        .saturating_sub(4)
+        .max(min);
+    let mut body = vec![0u8; len];
+    r.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn message(buf: &mut Vec<u8>, kind: u8, body: &[u8]) {
+    buf.push(kind);
+    buf.extend((body.len() as u32 + 4).to_be_bytes());
+    buf.extend(body);
+}
@@ -0,0 +1,253 @@
+//! End-to-end tests of import and bench against JoinBase, in which the MQTT broker is the `sink`
+//! and the pg wire endpoint is a stand-in answering simple queries, so no server is needed.
+
+mod common;
+
+use common::{PgStandIn, Reply};
+use oidbs::{
+    bench::{dialect::Dialect, Bench, QueryRequestor},
+    gen::{Gen, Generator},
+    import::{Import, Importer},
+    model::{bundled_models, Model},
+    results::RunResult,
+    sink::SinkServer,
+};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::Arc,
+};
+
+const SERVER_VERSION: &str = "JoinBase stand-in 0.1";
+
+fn pstations() -> Vec<Model> {
+    bundled_models()
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.name == "pstations")
+        .collect()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oidbs_it_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Generates a small pstations dataset, returns its number of rows.
+fn gen_dataset(dir: &Path) -> u64 {
+    let gen = Gen::new(dir.to_str().unwrap())
+        .unwrap()
+        .model_parameters(r#"{"num_stations":10,"num_sensors":5}"#)
+        .interval_per_worker_sec(10);
+    let result = Generator::new(gen, pstations()).unwrap().run().unwrap();
+    result.num_lines["pstations"]
+}
+
+/// the head of the concurrency query of pstations before its first placeholder,
+/// with which every rendering sent to JoinBase starts
+fn concurrency_head() -> String {
+    let sql = pstations()[0].concurrency_query_for("joinbase").unwrap();
+    let head = &sql[..sql.find("{{").unwrap()];
+    Dialect::JoinBase.translate(head).unwrap()
+}
+
+/// the pg wire stand-in of JoinBase, which counts the rows as the messages received by the sink,
+/// and answers other queries with one row, or `error` for the queries starting with `fail_on`
+fn joinbase_pg(sink: Option<Arc<SinkServer>>, fail_on: Option<String>) -> PgStandIn {
+    PgStandIn::start(move |sql| {
+        let lower = sql.to_lowercase();
+        match (&sink, &fail_on) {
+            (_, Some(s)) if sql.starts_with(s.as_str()) => Reply::Error("failed on purpose".into()),
+            _ if lower == "select version()" => {
+                Reply::Rows(vec!["version"], vec![vec![Some(SERVER_VERSION.into())]])
+            }
+            (Some(sink), _) if lower.starts_with("select count(*) from") => Reply::Rows(
+                vec!["count"],
+                vec![vec![Some(sink.stats().messages.to_string())]],
+            ),
+            _ if lower.starts_with("select") => {
+                Reply::Rows(vec!["v"], vec![vec![Some("0".into())]])
+            }
+            _ => Reply::Command("CREATE TABLE"),
+        }
+    })
+}
+
+fn oidbs(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_oidbs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_import_to_joinbase() {
+    let dir = temp_dir("import");
+    let num_rows = gen_dataset(&dir);
+    assert!(num_rows > 0);
+    let sink = Arc::new(SinkServer::start("127.0.0.1:0", false).unwrap());
+    let pg = joinbase_pg(Some(sink.clone()), None);
+
+    let import = Import::new(dir.to_str().unwrap())
+        .unwrap()
+        .ib_srv_part_mqtt(format!("abc:abc@{}", sink.local_addr()))
+        .ib_srv_part_pg(pg.srv_part())
+        .verify_wait_secs(10);
+    let result = Importer::new(import, pstations()).unwrap().run().unwrap();
+    assert_eq!(result.storage.num_rows, num_rows);
+    let stats = sink.stats();
+    assert_eq!(stats.messages, num_rows);
+    assert!(stats.payload_bytes > 0);
+
+    // the schemas are set up before importing, and the row count is verified after
+    let schema = &pstations()[0].target_infos["joinbase"].schema;
+    let queries = pg.queries();
+    assert_eq!(queries.first(), Some(schema));
+    assert!(queries
+        .iter()
+        .any(|q| q.starts_with("select count(*) from")));
+
+    // the rows are published in batches without setting up schemas
+    let sink = SinkServer::start("127.0.0.1:0", false).unwrap();
+    let pg = joinbase_pg(None, None);
+    let import = Import::new(dir.to_str().unwrap())
+        .unwrap()
+        .ib_srv_part_mqtt(format!("abc:abc@{}", sink.local_addr()))
+        .ib_srv_part_pg(pg.srv_part())
+        .num_rows_in_batch(100)
+        .import_data_only(true)
+        .skip_verify(true)
+        .quiet(true);
+    Importer::new(import, pstations()).unwrap().run().unwrap();
+    let stats = sink.stop().unwrap();
+    assert!(stats.messages >= num_rows.div_ceil(100));
+    assert!(stats.messages < num_rows);
+    assert!(pg.queries().is_empty());
+
+    // lost rows fail the verification
+    let sink = SinkServer::start("127.0.0.1:0", false).unwrap();
+    let pg = joinbase_pg(None, None);
+    let import = Import::new(dir.to_str().unwrap())
+        .unwrap()
+        .ib_srv_part_mqtt(format!("abc:abc@{}", sink.local_addr()))
+        .ib_srv_part_pg(pg.srv_part())
+        .import_data_only(true)
+        .verify_wait_secs(1);
+    let e = Importer::new(import, pstations())
+        .unwrap()
+        .run()
+        .unwrap_err();
+    assert_eq!(e.exit_code(), 7);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_bench_latency() {
+    let dir = temp_dir("latency");
+    let output = dir.join("result.json");
+    let pg = joinbase_pg(None, None);
+    let out = oidbs(&[
+        "bench",
+        "-i",
+        &pg.srv_part(),
+        "-r",
+        "2",
+        "-o",
+        output.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let result = RunResult::read_from(&output).unwrap();
+    assert_eq!(result.measurement_mode, "latency");
+    assert_eq!(result.server_version.as_deref(), Some(SERVER_VERSION));
+    assert_eq!(result.queries.len(), 8);
+    for q in &result.queries {
+        assert_eq!(q.samples_us.len(), 2);
+        assert_eq!((q.num_errors, q.num_timeouts), (0, 0));
+        assert!(stdout.contains(&q.desc), "{} not reported", q.desc);
+        assert_eq!(pg.queries().iter().filter(|s| **s == q.sql).count(), 2);
+    }
+    assert!(stdout.contains("Best Query Latency"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_bench_concurrency() {
+    let pg = joinbase_pg(None, None);
+    let bench = Bench::new("joinbase", "pstations")
+        .unwrap()
+        .ib_srv_part_pg(pg.srv_part())
+        .measurement_mode("concurrency")
+        .num_concurrent_threads(4)
+        .warmup_times(1)
+        .run_times(5);
+    let result = QueryRequestor::new(bench, pstations())
+        .unwrap()
+        .run()
+        .unwrap();
+    let c = result.concurrency.unwrap();
+    assert_eq!((c.num_threads, c.num_ok, c.num_errors), (4, 20, 0));
+    assert_eq!(c.samples_us.len(), 20);
+    assert!(c.qps > 0.0);
+    // the warm-up queries are sent too
+    let head = concurrency_head();
+    assert_eq!(
+        c.sql,
+        pstations()[0].concurrency_query_for("joinbase").unwrap()
+    );
+    let queries: Vec<_> = pg
+        .queries()
+        .into_iter()
+        .filter(|q| q.starts_with(&head))
+        .collect();
+    assert_eq!(queries.len(), 24);
+    // the placeholders are drawn per execution
+    assert!(queries.iter().any(|q| *q != queries[0]));
+
+    let bench = Bench::new("joinbase", "pstations")
+        .unwrap()
+        .ib_srv_part_pg(pg.srv_part())
+        .measurement_mode("concurrency")
+        .warmup_times(1)
+        .run_times(2)
+        .threads("1,2")
+        .quiet(true);
+    let result = QueryRequestor::new(bench, pstations())
+        .unwrap()
+        .run()
+        .unwrap();
+    let levels: Vec<_> = result
+        .sweep
+        .iter()
+        .map(|c| (c.num_threads, c.num_ok))
+        .collect();
+    assert_eq!(levels, [(1, 2), (2, 4)]);
+
+    // the failed queries exceed the allowed error rate
+    let pg = joinbase_pg(None, Some(head));
+    let out = oidbs(&[
+        "bench",
+        "-i",
+        &pg.srv_part(),
+        "-c",
+        "concurrency",
+        "-m",
+        "2",
+        "-w",
+        "1",
+        "-r",
+        "2",
+    ]);
+    assert_eq!(out.status.code(), Some(8));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.starts_with("error:"), "{}", stderr);
+}